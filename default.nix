@@ -1,4 +1,7 @@
 { localSystem ? { system = builtins.currentSystem; }
+  # Hex of the public key that signs asset descriptors; see docs/apdu.md. Builds without the
+  # debug_instructions feature need it.
+, assetDescriptorKey ? null
 }:
 
 rec {
@@ -57,6 +60,9 @@ rec {
               sdk = lib.findFirst (p: lib.hasPrefix "rust_ledger_device_sdk" p.name) (builtins.throw "no sdk!") attrs.dependencies;
             in {
               preHook = collection.gccLibsPreHook;
+              preConfigure = lib.optionalString (assetDescriptorKey != null) ''
+                export ASSET_DESCRIPTOR_KEY="${assetDescriptorKey}"
+              '';
               extraRustcOpts = attrs.extraRustcOpts or [] ++ [
                 "-C" "linker=${sdk.link_wrap}/bin/link_wrap.sh"
                 "-C" "link-arg=-T${sdk.lib}/lib/ledger_device_sdk.out/link.ld"
@@ -163,7 +169,9 @@ rec {
   apiPort = 5005;
 
   # Tests don't yet run on Darwin
-  runTests = { appExe, device, variant ? "", speculosCmd }:
+  # With promptLog, the app must be built with the `speculos` feature and info-level logging;
+  # the tests then check prompts against the records it logs rather than against OCR.
+  runTests = { appExe, device, variant ? "", speculosCmd, speculosModel, promptLog ? false }:
   if pkgs.stdenv.hostPlatform.isDarwin
   then null
   else
@@ -176,7 +184,12 @@ rec {
     mkdir $out
     (
     set +e # Dont exit on error, do the cleanup/kill of background processes
-    ${toString speculosCmd} ${appExe} --display headless &
+    export SPECULOS_MODEL=${speculosModel}
+    ${lib.optionalString promptLog ''
+      export PYTHONUNBUFFERED=1 PROMPT_LOG="$PWD/speculos.log"
+      touch "$PROMPT_LOG"
+    ''}
+    ${toString speculosCmd} ${appExe} --display headless ${lib.optionalString promptLog ''> >(tee -a "$PROMPT_LOG") 2>&1''} &
     SPECULOS=$!
 
     until wget -O/dev/null -o/dev/null http://localhost:${toString apiPort}; do sleep 0.1; done;
@@ -205,10 +218,14 @@ rec {
 
   appForDevice = device: rec {
     app = makeApp { inherit device; };
+    app-for-tests = makeApp {
+      inherit device;
+      rootFeatures = [ "default" "debug_instructions" ];
+    };
     app-with-logging = makeApp {
       inherit device;
       release = false;
-      rootFeatures = [ "default" "speculos" "extra_debug" ];
+      rootFeatures = [ "default" "speculos" "extra_debug" "debug_instructions" ];
     };
 
     memLimit = {
@@ -225,6 +242,7 @@ rec {
     };
 
     rootCrate = app.rootCrate.build;
+    rootCrate-for-tests = app-for-tests.rootCrate.build;
     rootCrate-with-logging = app-with-logging.rootCrate.build;
 
     appExe = rootCrate + "/bin/" + appName;
@@ -269,20 +287,32 @@ rec {
 
     tarballShell = import (archiveSource + "/${appName}-${device}/shell.nix");
 
-    speculosDeviceFlags = {
-      nanos = [ "-m" "nanos" ];
-      nanosplus = [ "-m" "nanosp" ];
-      nanox = [ "-m" "nanox" ];
+    speculosModel = {
+      nanos = "nanos";
+      nanosplus = "nanosp";
+      nanox = "nanox";
+      stax = "stax";
+      flex = "flex";
     }.${device} or (throw "Unknown target device: `${device}'");
 
+    speculosDeviceFlags = [ "-m" speculosModel ];
+
+    # The ts-tests only check Stax and Flex prompts against the prompt log.
+    touch = builtins.elem device [ "stax" "flex" ];
+
     speculosCmd = [
       "speculos"
       "--api-port" (toString apiPort)
     ] ++ speculosDeviceFlags;
 
-    test = runTests { inherit appExe speculosCmd device; };
+    test = runTests {
+      inherit speculosCmd speculosModel device;
+      promptLog = touch;
+      appExe = (if touch then rootCrate-with-logging else rootCrate-for-tests) + "/bin/" + appName;
+    };
     test-with-logging = runTests {
-      inherit speculosCmd device;
+      inherit speculosCmd speculosModel device;
+      promptLog = true;
       appExe = rootCrate-with-logging + "/bin/" + appName;
       variant = "-with-logging";
     };
@@ -1,3 +1,5 @@
+{ assetDescriptorKey ? null }:
+
 let
   alamgu-path = import ./dep/alamgu/thunk.nix;
   pkgsSrc = import (alamgu-path + "/dep/nixpkgs/thunk.nix");
@@ -5,6 +7,7 @@ let
 
   perSystem = lib.genAttrs [ "x86_64-linux" "x86_64-darwin" ] (system: import ./. {
      localSystem = { inherit system; };
+     inherit assetDescriptorKey;
   });
 in {
   inherit (perSystem) x86_64-linux x86_64-darwin;
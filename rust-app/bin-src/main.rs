@@ -4,7 +4,10 @@
 #[cfg(not(target_family = "bolos"))]
 fn main() {}
 
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
 use alamgu_example::main_nanos::*;
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+use alamgu_example::main_stax::*;
 
 ledger_device_sdk::set_panic!(ledger_device_sdk::exiting_panic);
 
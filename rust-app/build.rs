@@ -1,3 +1,57 @@
+use std::{env, fs, path::Path};
+
 fn main() {
     println!("cargo:rerun-if-changed=script.ld");
+
+    // The public key that signs asset descriptors, as the 65 byte uncompressed point
+    // (0x04 | x | y, big endian) that cx_ecfp_public_key_t holds, from ASSET_DESCRIPTOR_KEY.
+    // Only builds for the ts-tests may fall back to the test key they sign with, as its private
+    // key is public.
+    println!("cargo:rerun-if-env-changed=ASSET_DESCRIPTOR_KEY");
+    println!("cargo:rerun-if-changed=asset-descriptor-test-key.hex");
+    let hex = match env::var("ASSET_DESCRIPTOR_KEY") {
+        Ok(key) => key,
+        Err(_) if env::var_os("CARGO_FEATURE_DEBUG_INSTRUCTIONS").is_some() => {
+            fs::read_to_string("asset-descriptor-test-key.hex").unwrap()
+        }
+        Err(_) => panic!(
+            "ASSET_DESCRIPTOR_KEY must be set; only debug_instructions builds may use the test key"
+        ),
+    };
+    let hex = hex.trim();
+    if hex.len() != 130 || !hex.starts_with("04") {
+        panic!("the asset descriptor key must be 65 bytes of hex starting with 04");
+    }
+    let bytes: Vec<String> = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            let byte = u8::from_str_radix(&hex[i..i + 2], 16)
+                .expect("the asset descriptor key must be hex");
+            format!("{byte:#04x}")
+        })
+        .collect();
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("asset_descriptor_key.rs"),
+        format!(
+            "pub const ASSET_DESCRIPTOR_KEY: [u8; 65] = [{}];\n",
+            bytes.join(", ")
+        ),
+    )
+    .unwrap();
+
+    // The largest transaction SIGN accepts, in bytes. Anything larger is refused before the app
+    // starts hashing it, so that a host can't keep the device busy indefinitely.
+    println!("cargo:rerun-if-env-changed=MAX_TX_SIZE");
+    let max_tx_size: usize = match env::var("MAX_TX_SIZE") {
+        Ok(size) => size
+            .trim()
+            .parse()
+            .expect("MAX_TX_SIZE must be a number of bytes"),
+        Err(_) => 16 * 1024,
+    };
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("max_tx_size.rs"),
+        format!("pub const MAX_TX_SIZE: usize = {max_tx_size};\n"),
+    )
+    .unwrap();
 }
@@ -0,0 +1,268 @@
+//! Fixed-point amounts for display.
+//!
+//! Base units are printed as a decimal amount with `,` thousands separators, without trailing
+//! zeros in the fractional part, and followed by the ticker if there is one:
+//! `Amount::new(1_234_500_000, 6).ticker("EXA")` displays as `1,234.5 EXA`. Formatting goes
+//! straight to the `Write` it is given, so prompts can use it without a buffer.
+
+use core::fmt;
+
+/// Enough digits for `u128::MAX`.
+const MAX_DIGITS: usize = 39;
+
+/// `value` base units of an asset with `decimals` decimal places.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Amount<'a> {
+    value: u128,
+    decimals: u8,
+    ticker: &'a str,
+}
+
+impl<'a> Amount<'a> {
+    pub fn new(value: impl Into<u128>, decimals: u8) -> Self {
+        Amount {
+            value: value.into(),
+            decimals,
+            ticker: "",
+        }
+    }
+
+    pub fn ticker(mut self, ticker: &'a str) -> Self {
+        self.ticker = ticker;
+        self
+    }
+}
+
+impl fmt::Display for Amount<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_amount(f, self.value, self.decimals, self.ticker)
+    }
+}
+
+/// Writes `value` base units with `decimals` decimal places, then the ticker if it is not empty.
+pub fn write_amount(
+    w: &mut dyn fmt::Write,
+    value: u128,
+    decimals: u8,
+    ticker: &str,
+) -> fmt::Result {
+    let mut buffer = [0; MAX_DIGITS];
+    let digits = to_digits(value, &mut buffer);
+    let decimals = decimals as usize;
+
+    // Split into integer and fractional digits; the fraction may need leading zeros that are not
+    // in `digits` when there are more decimals than digits.
+    let (integer, fraction, leading_zeros) = if digits.len() > decimals {
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        (integer, fraction, 0)
+    } else {
+        (&b"0"[..], digits, decimals - digits.len())
+    };
+    let significant = fraction.len() - fraction.iter().rev().take_while(|d| **d == b'0').count();
+
+    write_grouped(w, integer)?;
+    if significant > 0 {
+        w.write_char('.')?;
+        for _ in 0..leading_zeros {
+            w.write_char('0')?;
+        }
+        write_digits(w, &fraction[..significant])?;
+    }
+    if !ticker.is_empty() {
+        w.write_char(' ')?;
+        w.write_str(ticker)?;
+    }
+    Ok(())
+}
+
+/// Writes a run of ASCII digits with a `,` before every group of three counted from the right.
+fn write_grouped(w: &mut dyn fmt::Write, digits: &[u8]) -> fmt::Result {
+    let first = match digits.len() % 3 {
+        0 => 3.min(digits.len()),
+        n => n,
+    };
+    write_digits(w, &digits[..first])?;
+    for group in digits[first..].chunks(3) {
+        w.write_char(',')?;
+        write_digits(w, group)?;
+    }
+    Ok(())
+}
+
+fn write_digits(w: &mut dyn fmt::Write, digits: &[u8]) -> fmt::Result {
+    // Only ever called with ASCII digits from `to_digits`.
+    w.write_str(core::str::from_utf8(digits).map_err(|_| fmt::Error)?)
+}
+
+/// The decimal digits of `value` as ASCII, most significant first, with no leading zeros except
+/// for `0` itself.
+fn to_digits(mut value: u128, buffer: &mut [u8; MAX_DIGITS]) -> &[u8] {
+    let mut start = MAX_DIGITS;
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buffer[start..];
+        }
+    }
+}
+
+#[cfg(all(test, not(target_family = "bolos")))]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::format;
+    use std::string::{String, ToString};
+
+    fn show(value: u128, decimals: u8, ticker: &str) -> String {
+        format!("{}", Amount::new(value, decimals).ticker(ticker))
+    }
+
+    /// Straightforward string-based version to check `write_amount` against.
+    fn reference(value: u128, decimals: u8) -> String {
+        let decimals = decimals as usize;
+        let mut digits = value.to_string();
+        if digits.len() <= decimals {
+            digits = "0".repeat(decimals + 1 - digits.len()) + &digits;
+        }
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        let mut grouped = String::new();
+        for (i, c) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(c);
+        }
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            grouped
+        } else {
+            grouped + "." + fraction
+        }
+    }
+
+    #[test]
+    fn zero() {
+        assert_eq!(show(0, 0, ""), "0");
+        assert_eq!(show(0, 6, ""), "0");
+        assert_eq!(show(0, 255, ""), "0");
+        assert_eq!(show(0, 6, "EXA"), "0 EXA");
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(show(1, 0, ""), "1");
+        assert_eq!(show(999, 0, ""), "999");
+        assert_eq!(show(1_000, 0, ""), "1,000");
+        assert_eq!(show(12_345, 0, ""), "12,345");
+        assert_eq!(show(999_999, 0, ""), "999,999");
+        assert_eq!(show(1_000_000, 0, ""), "1,000,000");
+    }
+
+    #[test]
+    fn trims_trailing_zeros() {
+        assert_eq!(show(1_000_000, 6, ""), "1");
+        assert_eq!(show(1_500_000, 6, ""), "1.5");
+        assert_eq!(show(1_234_500_000, 6, "EXA"), "1,234.5 EXA");
+        assert_eq!(show(10, 1, ""), "1");
+        assert_eq!(show(100, 1, ""), "10");
+    }
+
+    #[test]
+    fn small_fractions() {
+        assert_eq!(show(1, 1, ""), "0.1");
+        assert_eq!(show(1, 6, ""), "0.000001");
+        assert_eq!(show(10, 6, ""), "0.00001");
+        assert_eq!(show(123, 3, ""), "0.123");
+        assert_eq!(show(1, 18, "ETH"), "0.000000000000000001 ETH");
+        assert_eq!(
+            show(1, 40, ""),
+            "0.0000000000000000000000000000000000000001"
+        );
+    }
+
+    #[test]
+    fn max_values() {
+        assert_eq!(show(u64::MAX as u128, 0, ""), "18,446,744,073,709,551,615");
+        assert_eq!(show(u64::MAX as u128, 9, ""), "18,446,744,073.709551615");
+        assert_eq!(show(u64::MAX as u128, 20, ""), "0.18446744073709551615");
+        assert_eq!(
+            show(u128::MAX, 0, ""),
+            "340,282,366,920,938,463,463,374,607,431,768,211,455"
+        );
+        assert_eq!(
+            show(u128::MAX, 18, "EXA"),
+            "340,282,366,920,938,463,463.374607431768211455 EXA"
+        );
+        assert_eq!(
+            show(u128::MAX, 39, ""),
+            "0.340282366920938463463374607431768211455"
+        );
+        assert_eq!(show(u128::MAX, 255, "").len(), 2 + 255);
+    }
+
+    #[test]
+    fn accepts_u64() {
+        assert_eq!(
+            format!("{}", Amount::new(u64::MAX, 6)),
+            "18,446,744,073,709.551615"
+        );
+        assert_eq!(format!("{}", Amount::new(42u64, 0).ticker("X")), "42 X");
+    }
+
+    #[test]
+    fn matches_reference() {
+        for decimals in 0..=12 {
+            for value in (0..=20_000).chain((0..64).map(|shift| 1u128 << shift)) {
+                assert_eq!(
+                    show(value, decimals, ""),
+                    reference(value, decimals),
+                    "value {} decimals {}",
+                    value,
+                    decimals
+                );
+            }
+        }
+        for decimals in 0..=255 {
+            for value in [1, 10, 999, 1_000, u64::MAX as u128, u128::MAX] {
+                assert_eq!(show(value, decimals, ""), reference(value, decimals));
+            }
+        }
+    }
+
+    /// A `Write` that fails once it has taken `room` bytes, like a full prompt buffer.
+    struct Limited {
+        room: usize,
+        written: String,
+    }
+
+    impl fmt::Write for Limited {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if self.written.len() + s.len() > self.room {
+                return Err(fmt::Error);
+            }
+            self.written.push_str(s);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn propagates_write_errors() {
+        let full = show(1_234_500_000, 6, "EXA");
+        for room in 0..full.len() {
+            let mut w = Limited {
+                room,
+                written: String::new(),
+            };
+            assert!(write_amount(&mut w, 1_234_500_000, 6, "EXA").is_err());
+            assert!(full.starts_with(&w.written));
+        }
+        let mut w = Limited {
+            room: full.len(),
+            written: String::new(),
+        };
+        assert!(write_amount(&mut w, 1_234_500_000, 6, "EXA").is_ok());
+        assert_eq!(w.written, full);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::interface::*;
+use crate::utils::parse_exactly;
+use alamgu_async_block::*;
+use arrayvec::{ArrayString, ArrayVec};
+use core::cell::RefCell;
+use ledger_device_sdk::io::SyscallError;
+use ledger_parser_combinators::async_parser::*;
+use ledger_parser_combinators::interp::*;
+use ledger_secure_sdk_sys::{
+    cx_ecfp_public_key_t, cx_eddsa_verify_no_throw, CX_CURVE_Ed25519, CX_SHA512,
+};
+
+include!(concat!(env!("OUT_DIR"), "/asset_descriptor_key.rs"));
+
+/// Prepended to a descriptor's fields to form the message its signature covers.
+const ASSET_DESCRIPTOR_DOMAIN: &[u8] = b"alamgu-example asset descriptor";
+
+/// Descriptors kept for the session; providing another one evicts the oldest.
+const MAX_CACHED_ASSETS: usize = 4;
+
+pub const NATIVE_TICKER: &str = "EXA";
+pub const NATIVE_DECIMALS: u8 = 9;
+
+/// What a transfer needs to display an amount of an asset.
+#[derive(Clone)]
+pub struct AssetInfo {
+    pub chain_id: u32,
+    pub asset_id: [u8; 32],
+    pub ticker: ArrayString<MAX_TICKER_LEN>,
+    pub decimals: u8,
+}
+
+// The app is single-threaded, so the cache needs no locking. It lives in RAM and so only lasts
+// until the app exits.
+struct AssetCache(RefCell<ArrayVec<AssetInfo, MAX_CACHED_ASSETS>>);
+unsafe impl Sync for AssetCache {}
+
+static ASSETS: AssetCache = AssetCache(RefCell::new(ArrayVec::new_const()));
+
+/// The asset `asset_id` on `chain_id`: the native asset for id zero, otherwise the descriptor
+/// provided this session, if any.
+pub fn asset_info(chain_id: u32, asset_id: &[u8; 32]) -> Option<AssetInfo> {
+    if asset_id == &[0; 32] {
+        return Some(AssetInfo {
+            chain_id,
+            asset_id: *asset_id,
+            ticker: ArrayString::from(NATIVE_TICKER).ok()?,
+            decimals: NATIVE_DECIMALS,
+        });
+    }
+    ASSETS
+        .0
+        .borrow()
+        .iter()
+        .find(|asset| asset.chain_id == chain_id && &asset.asset_id == asset_id)
+        .cloned()
+}
+
+pub type AssetDescriptorParserImplT = impl AsyncParser<AssetDescriptorParameters, ByteStream>
+    + HasOutput<
+        AssetDescriptorParameters,
+        Output = (
+            ([u8; 32], (ArrayVec<u8, MAX_TICKER_LEN>, (u8, u32))),
+            [u8; 64],
+        ),
+    >;
+pub const ASSET_DESCRIPTOR_PARSER: AssetDescriptorParserImplT = (
+    (
+        DefaultInterp,
+        (SubInterp(DefaultInterp), (DefaultInterp, DefaultInterp)),
+    ),
+    DefaultInterp,
+);
+
+pub async fn provide_asset_descriptor_apdu(io: HostIO) {
+    let input = match io.get_params::<1>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let ((asset_id, (ticker, (decimals, chain_id))), signature) =
+        parse_exactly(&ASSET_DESCRIPTOR_PARSER, &mut input[0].clone()).await;
+
+    let mut message = ArrayVec::<u8, 128>::new();
+    let _ = message.try_extend_from_slice(ASSET_DESCRIPTOR_DOMAIN);
+    let _ = message.try_extend_from_slice(&asset_id);
+    let _ = message.try_push(ticker.len() as u8);
+    let _ = message.try_extend_from_slice(&ticker);
+    let _ = message.try_push(decimals);
+    let _ = message.try_extend_from_slice(&chain_id.to_le_bytes());
+
+    if !verify_descriptor(&message, &signature) {
+        reject::<()>(SyscallError::Security as u16).await;
+    }
+
+    // Tickers are shown as-is, so only printable ASCII is allowed.
+    let ticker = match core::str::from_utf8(&ticker) {
+        Ok(t) if !t.is_empty() && t.bytes().all(|c| c.is_ascii_graphic()) => t,
+        _ => reject(SyscallError::InvalidParameter as u16).await,
+    };
+    let asset = AssetInfo {
+        chain_id,
+        asset_id,
+        ticker: ArrayString::from(ticker).unwrap_or_default(),
+        decimals,
+    };
+
+    {
+        let mut assets = ASSETS.0.borrow_mut();
+        assets.retain(|a| a.chain_id != chain_id || a.asset_id != asset_id);
+        if assets.is_full() {
+            assets.remove(0);
+        }
+        assets.push(asset);
+    }
+
+    io.result_final(&[]).await;
+}
+
+fn verify_descriptor(message: &[u8], signature: &[u8; 64]) -> bool {
+    let key = cx_ecfp_public_key_t {
+        curve: CX_CURVE_Ed25519,
+        W_len: ASSET_DESCRIPTOR_KEY.len(),
+        W: ASSET_DESCRIPTOR_KEY,
+    };
+    unsafe {
+        cx_eddsa_verify_no_throw(
+            &key,
+            CX_SHA512,
+            message.as_ptr(),
+            message.len(),
+            signature.as_ptr(),
+            signature.len(),
+        )
+    }
+}
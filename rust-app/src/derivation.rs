@@ -0,0 +1,341 @@
+//! Public keys, signatures and X25519 scalars for each `DerivationScheme`.
+//!
+//! The legacy and SLIP-10 schemes go through `ledger_crypto_helpers`, which hands the derived
+//! key to the OS as an RFC 8032 private key. BIP32-Ed25519 keys are extended keys, the scalar and
+//! nonce key already expanded, so they are given to the OS as 64 byte private keys, which it uses
+//! as they are. BIP340 keys are BIP32 secp256k1 keys, which the OS signs with in its BIP340 mode.
+
+use crate::interface::DerivationScheme;
+use arrayvec::ArrayVec;
+use ledger_crypto_helpers::common::{Address, CryptographyError};
+use ledger_crypto_helpers::eddsa::{
+    eddsa_sign, with_private_key, with_public_keys, Ed25519RawPubKeyAddress,
+};
+use ledger_device_sdk::ecc::{bip32_derive, CurvesId};
+use ledger_secure_sdk_sys::*;
+use zeroize::{Zeroize, Zeroizing};
+
+#[allow(clippy::upper_case_acronyms)]
+type PKH = Ed25519RawPubKeyAddress;
+
+/// A BIP32-Ed25519 key: the private key `kL | kR`, its public key and its chain code.
+pub struct ExtendedKey {
+    private: cx_ecfp_512_private_key_t,
+    pub public: [u8; 32],
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    fn derive(path: &[u32]) -> Result<ExtendedKey, CryptographyError> {
+        let mut raw = Zeroizing::new([0u8; 64]);
+        let mut chain_code = [0u8; 32];
+        bip32_derive(
+            CurvesId::Ed25519,
+            path,
+            &mut raw[..],
+            Some(&mut chain_code[..]),
+        )
+        .map_err(|_| CryptographyError::NoneError)?;
+
+        let mut key = ExtendedKey {
+            private: unsafe { core::mem::zeroed() },
+            public: [0; 32],
+            chain_code,
+        };
+        let mut public: cx_ecfp_public_key_t = unsafe { core::mem::zeroed() };
+        let ok = unsafe {
+            cx_ecfp_init_private_key_no_throw(
+                CX_CURVE_Ed25519,
+                raw.as_ptr(),
+                raw.len(),
+                key.private_ptr(),
+            ) == CX_OK
+                && cx_eddsa_get_public_key_no_throw(
+                    key.private_ptr(),
+                    CX_SHA512,
+                    &mut public,
+                    core::ptr::null_mut(),
+                    0,
+                    core::ptr::null_mut(),
+                    0,
+                ) == CX_OK
+        };
+        if !ok {
+            return Err(CryptographyError::NoneError);
+        }
+        key.public = encode_ed25519(&public);
+        Ok(key)
+    }
+
+    /// The OS takes 64 byte ed25519 keys through the pointer type of the 32 byte ones.
+    fn private_ptr(&mut self) -> *mut cx_ecfp_private_key_t {
+        &mut self.private as *mut cx_ecfp_512_private_key_t as *mut cx_ecfp_private_key_t
+    }
+
+    fn sign(&mut self, message: &[u8]) -> Result<[u8; 64], CryptographyError> {
+        let mut sig = [0u8; 64];
+        if unsafe {
+            cx_eddsa_sign_no_throw(
+                self.private_ptr(),
+                CX_SHA512,
+                message.as_ptr(),
+                message.len(),
+                sig.as_mut_ptr(),
+                sig.len(),
+            )
+        } != CX_OK
+        {
+            return Err(CryptographyError::NoneError);
+        }
+        Ok(sig)
+    }
+}
+
+impl Drop for ExtendedKey {
+    fn drop(&mut self) {
+        self.private.d.zeroize();
+    }
+}
+
+/// The RFC 8032 encoding of an OS ed25519 public key. W is 04 | x | y, both big endian; the
+/// encoding is y little endian with the sign of x in the top bit.
+fn encode_ed25519(public: &cx_ecfp_public_key_t) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    for (out, byte) in key.iter_mut().zip(public.W[33..65].iter().rev()) {
+        *out = *byte;
+    }
+    key[31] |= (public.W[32] & 1) << 7;
+    key
+}
+
+/// The SLIP-10 ed25519 public key and chain code at `path` from `seed` instead of the device's
+/// seed, so that the ts-tests can check the OS's SLIP-10 derivation against SLIP-10's test
+/// vectors.
+#[cfg(feature = "debug_instructions")]
+pub fn slip10_with_seed(
+    seed: &[u8],
+    path: &[u32],
+) -> Result<([u8; 32], [u8; 32]), CryptographyError> {
+    let mut seed: ArrayVec<u8, 64> = seed.iter().copied().collect();
+    let mut raw = Zeroizing::new([0u8; 64]);
+    let mut chain_code = [0u8; 32];
+    let mut private: cx_ecfp_private_key_t = unsafe { core::mem::zeroed() };
+    let mut public: cx_ecfp_public_key_t = unsafe { core::mem::zeroed() };
+    let ok = unsafe {
+        os_derive_bip32_with_seed_no_throw(
+            HDW_ED25519_SLIP10,
+            CX_CURVE_Ed25519,
+            path.as_ptr(),
+            path.len() as u32,
+            raw.as_mut_ptr(),
+            chain_code.as_mut_ptr(),
+            seed.as_mut_ptr(),
+            seed.len() as u32,
+        ) == CX_OK
+            && cx_ecfp_init_private_key_no_throw(CX_CURVE_Ed25519, raw.as_ptr(), 32, &mut private)
+                == CX_OK
+            && cx_eddsa_get_public_key_no_throw(
+                &private,
+                CX_SHA512,
+                &mut public,
+                core::ptr::null_mut(),
+                0,
+                core::ptr::null_mut(),
+                0,
+            ) == CX_OK
+    };
+    private.d.zeroize();
+    if !ok {
+        return Err(CryptographyError::NoneError);
+    }
+    Ok((encode_ed25519(&public), chain_code))
+}
+
+/// Calls `f` with the BIP32-Ed25519 key at `path`.
+pub fn with_extended_key<A>(
+    path: &[u32],
+    f: impl FnOnce(&ExtendedKey) -> Result<A, CryptographyError>,
+) -> Result<A, CryptographyError> {
+    f(&ExtendedKey::derive(path)?)
+}
+
+/// A secp256k1 private key, for BIP340 signatures.
+pub struct Secp256k1Key {
+    private: cx_ecfp_private_key_t,
+}
+
+impl Secp256k1Key {
+    /// The BIP32 secp256k1 key at `path`.
+    fn derive(path: &[u32]) -> Result<Secp256k1Key, CryptographyError> {
+        // The OS writes 64 bytes of key for any curve; secp256k1 keys are the first 32.
+        let mut raw = Zeroizing::new([0u8; 64]);
+        bip32_derive(CurvesId::Secp256k1, path, &mut raw[..], None)
+            .map_err(|_| CryptographyError::NoneError)?;
+        let mut secret = Zeroizing::new([0u8; 32]);
+        secret.copy_from_slice(&raw[..32]);
+        Secp256k1Key::from_secret(&secret)
+    }
+
+    pub fn from_secret(secret: &[u8; 32]) -> Result<Secp256k1Key, CryptographyError> {
+        let mut key = Secp256k1Key {
+            private: unsafe { core::mem::zeroed() },
+        };
+        if unsafe {
+            cx_ecfp_init_private_key_no_throw(
+                CX_CURVE_SECP256K1,
+                secret.as_ptr(),
+                secret.len(),
+                &mut key.private,
+            )
+        } != CX_OK
+        {
+            return Err(CryptographyError::NoneError);
+        }
+        Ok(key)
+    }
+
+    /// The x-only public key of BIP340.
+    pub fn public_key(&mut self) -> Result<[u8; 32], CryptographyError> {
+        let mut public: cx_ecfp_public_key_t = unsafe { core::mem::zeroed() };
+        if unsafe {
+            cx_ecfp_generate_pair_no_throw(CX_CURVE_SECP256K1, &mut public, &mut self.private, true)
+        } != CX_OK
+        {
+            return Err(CryptographyError::NoneError);
+        }
+        // W is 04 | x | y, big endian.
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&public.W[1..33]);
+        Ok(key)
+    }
+
+    /// A BIP340 signature of a 32 byte message, with auxiliary randomness the OS draws.
+    pub fn sign(&self, message: &[u8]) -> Result<[u8; 64], CryptographyError> {
+        if message.len() != 32 {
+            return Err(CryptographyError::NoneError);
+        }
+        let mut sig = [0u8; 64];
+        let mut sig_len = sig.len();
+        if unsafe {
+            cx_ecschnorr_sign_no_throw(
+                &self.private,
+                CX_ECSCHNORR_BIP0340 | CX_RND_TRNG,
+                CX_SHA256,
+                message.as_ptr(),
+                message.len(),
+                sig.as_mut_ptr(),
+                &mut sig_len,
+            )
+        } != CX_OK
+            || sig_len != sig.len()
+        {
+            return Err(CryptographyError::NoneError);
+        }
+        Ok(sig)
+    }
+}
+
+impl Drop for Secp256k1Key {
+    fn drop(&mut self) {
+        self.private.d.zeroize();
+    }
+}
+
+/// BIP340's tagged hash of `message`: `SHA-256(SHA-256(tag) | SHA-256(tag) | message)`, for
+/// messages of up to 192 bytes.
+pub fn tagged_hash(tag: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut tag_hash = [0u8; 32];
+    let mut input = ArrayVec::<u8, { 64 + 192 }>::new();
+    let mut hash = [0u8; 32];
+    unsafe {
+        cx_hash_sha256(
+            tag.as_ptr(),
+            tag.len(),
+            tag_hash.as_mut_ptr(),
+            tag_hash.len(),
+        );
+    }
+    let _ = input.try_extend_from_slice(&tag_hash);
+    let _ = input.try_extend_from_slice(&tag_hash);
+    if input.try_extend_from_slice(message).is_err() {
+        panic!("message too long for a tagged hash");
+    }
+    unsafe {
+        cx_hash_sha256(input.as_ptr(), input.len(), hash.as_mut_ptr(), hash.len());
+    }
+    hash
+}
+
+/// Calls `f` with the public key at `path`, which is also the address: the ed25519 public key,
+/// or the x-only secp256k1 one for BIP340.
+pub fn with_public_key<A>(
+    path: &[u32],
+    scheme: DerivationScheme,
+    f: impl FnOnce(&[u8; 32]) -> Result<A, CryptographyError>,
+) -> Result<A, CryptographyError> {
+    match scheme {
+        DerivationScheme::Legacy | DerivationScheme::Slip10 => {
+            with_public_keys(path, scheme == DerivationScheme::Slip10, |_, pkh: &PKH| {
+                let mut key = [0; 32];
+                key.copy_from_slice(pkh.get_binary_address());
+                f(&key)
+            })
+        }
+        DerivationScheme::Bip32Ed25519 => with_extended_key(path, |key| f(&key.public)),
+        DerivationScheme::Bip340 => f(&Secp256k1Key::derive(path)?.public_key()?),
+    }
+}
+
+/// Signs `message` with the key at `path`: an ed25519 signature, or a BIP340 one with fresh
+/// auxiliary randomness, of a message that must then be 32 bytes.
+pub fn sign(
+    path: &[u32],
+    scheme: DerivationScheme,
+    message: &[u8],
+) -> Result<[u8; 64], CryptographyError> {
+    match scheme {
+        DerivationScheme::Legacy | DerivationScheme::Slip10 => {
+            let sig = eddsa_sign(path, scheme == DerivationScheme::Slip10, message)?;
+            let mut rv = [0; 64];
+            rv.copy_from_slice(&sig.0[..]);
+            Ok(rv)
+        }
+        DerivationScheme::Bip32Ed25519 => ExtendedKey::derive(path)?.sign(message),
+        DerivationScheme::Bip340 => Secp256k1Key::derive(path)?.sign(message),
+    }
+}
+
+/// Calls `f` with the X25519 private scalar of the ed25519 key at `path`, unclamped. It is the
+/// ed25519 scalar, so the matching X25519 public key is the Montgomery form of the ed25519
+/// public key: as in RFC 8032 the first half of the SHA-512 hash of the private key, except for
+/// extended keys, whose first half is the scalar. BIP340 keys have none.
+pub fn with_x25519_scalar<A>(
+    path: &[u32],
+    scheme: DerivationScheme,
+    f: impl FnOnce(&[u8; 32]) -> A,
+) -> Result<A, CryptographyError> {
+    let mut scalar = Zeroizing::new([0u8; 32]);
+    match scheme {
+        DerivationScheme::Legacy | DerivationScheme::Slip10 => {
+            with_private_key(path, scheme == DerivationScheme::Slip10, |key| {
+                let mut hash = Zeroizing::new([0u8; 64]);
+                unsafe {
+                    cx_hash_sha512(
+                        key.d.as_ptr(),
+                        key.d_len as usize,
+                        hash.as_mut_ptr(),
+                        hash.len(),
+                    );
+                }
+                scalar.copy_from_slice(&hash[..32]);
+                Ok(())
+            })?
+        }
+        DerivationScheme::Bip32Ed25519 => {
+            let key = ExtendedKey::derive(path)?;
+            scalar.copy_from_slice(&key.private.d[..32]);
+        }
+        DerivationScheme::Bip340 => return Err(CryptographyError::NoneError),
+    }
+    Ok(f(&scalar))
+}
@@ -0,0 +1,249 @@
+//! Text encodings for hashes shown on screen.
+//!
+//! Hashes can be written as hex, URL-safe base64 or base58, optionally in blocks of four
+//! characters separated by spaces, so that the user can compare them with what the wallet shows.
+
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashFormat {
+    /// URL-safe base64 without padding.
+    Base64Url,
+    /// Lowercase hex.
+    Hex,
+    /// Base58 with the Bitcoin alphabet.
+    Base58,
+}
+
+impl HashFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashFormat::Base64Url => "Base64url",
+            HashFormat::Hex => "Hex",
+            HashFormat::Base58 => "Base58",
+        }
+    }
+
+    /// The format after this one, for cycling through them in a menu.
+    pub fn next(self) -> Self {
+        match self {
+            HashFormat::Base64Url => HashFormat::Hex,
+            HashFormat::Hex => HashFormat::Base58,
+            HashFormat::Base58 => HashFormat::Base64Url,
+        }
+    }
+}
+
+/// The longest input `write_hash` encodes, enough for a 512 bit hash.
+pub const MAX_HASH_LEN: usize = 64;
+
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Writes `bytes` in `format`, with a space after every four characters when `grouped`.
+pub fn write_hash(
+    w: &mut dyn fmt::Write,
+    bytes: &[u8],
+    format: HashFormat,
+    grouped: bool,
+) -> fmt::Result {
+    if bytes.len() > MAX_HASH_LEN {
+        return Err(fmt::Error);
+    }
+    let mut out = Grouped {
+        inner: w,
+        grouped,
+        in_block: 0,
+    };
+    match format {
+        HashFormat::Base64Url => write_base64url(&mut out, bytes),
+        HashFormat::Hex => bytes.iter().try_for_each(|b| {
+            out.push(HEX_ALPHABET[(b >> 4) as usize])?;
+            out.push(HEX_ALPHABET[(b & 0xf) as usize])
+        }),
+        HashFormat::Base58 => write_base58(&mut out, bytes),
+    }
+}
+
+const HEX_ALPHABET: &[u8; 16] = b"0123456789abcdef";
+
+/// Passes characters through, adding a space between blocks of four when grouping.
+struct Grouped<'a> {
+    inner: &'a mut dyn fmt::Write,
+    grouped: bool,
+    in_block: usize,
+}
+
+impl Grouped<'_> {
+    fn push(&mut self, c: u8) -> fmt::Result {
+        if self.grouped && self.in_block == 4 {
+            self.inner.write_char(' ')?;
+            self.in_block = 0;
+        }
+        self.in_block += 1;
+        self.inner.write_char(c as char)
+    }
+}
+
+fn write_base64url(out: &mut Grouped, bytes: &[u8]) -> fmt::Result {
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let sextets = [
+            b[0] >> 2,
+            (b[0] & 0x03) << 4 | b[1] >> 4,
+            (b[1] & 0x0f) << 2 | b[2] >> 6,
+            b[2] & 0x3f,
+        ];
+        // n input bytes make n + 1 output characters; there is no padding.
+        for s in &sextets[..chunk.len() + 1] {
+            out.push(BASE64URL_ALPHABET[*s as usize])?;
+        }
+    }
+    Ok(())
+}
+
+fn write_base58(out: &mut Grouped, bytes: &[u8]) -> fmt::Result {
+    // Each leading zero byte is written as a '1'; the rest is a big-endian number, converted by
+    // repeated division into base 58 digits, least significant first.
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    let mut digits = [0u8; MAX_HASH_LEN * 138 / 100 + 1];
+    let mut len = 0;
+    for byte in &bytes[zeros..] {
+        let mut carry = *byte as u32;
+        for digit in &mut digits[..len] {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits[len] = (carry % 58) as u8;
+            len += 1;
+            carry /= 58;
+        }
+    }
+    for _ in 0..zeros {
+        out.push(BASE58_ALPHABET[0])?;
+    }
+    for digit in digits[..len].iter().rev() {
+        out.push(BASE58_ALPHABET[*digit as usize])?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, not(target_family = "bolos")))]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::string::String;
+
+    fn show(bytes: &[u8], format: HashFormat, grouped: bool) -> String {
+        let mut s = String::new();
+        write_hash(&mut s, bytes, format, grouped).unwrap();
+        s
+    }
+
+    fn unhex(s: &str) -> std::vec::Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(show(&[], HashFormat::Hex, false), "");
+        assert_eq!(show(&[0x00, 0xab, 0xff], HashFormat::Hex, false), "00abff");
+        assert_eq!(
+            show(&[0x01, 0x23, 0x45, 0x67, 0x89], HashFormat::Hex, true),
+            "0123 4567 89"
+        );
+    }
+
+    #[test]
+    fn base64url() {
+        // RFC 4648 test vectors, without padding.
+        for (input, expected) in [
+            ("", ""),
+            ("f", "Zg"),
+            ("fo", "Zm8"),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg"),
+            ("fooba", "Zm9vYmE"),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(
+                show(input.as_bytes(), HashFormat::Base64Url, false),
+                expected
+            );
+        }
+        assert_eq!(show(&[0xfb, 0xff], HashFormat::Base64Url, false), "-_8");
+        // The hash the blind signing test expects.
+        assert_eq!(
+            show(
+                &unhex("c82f5cfd99f772345757cf6d25a4f85a30a35ec145e144169f602adac1e363ee"),
+                HashFormat::Base64Url,
+                false
+            ),
+            "yC9c_Zn3cjRXV89tJaT4WjCjXsFF4UQWn2Aq2sHjY-4"
+        );
+    }
+
+    #[test]
+    fn base58() {
+        // Vectors from the base58 draft RFC and Bitcoin Core's base58_encode_decode.json.
+        for (input, expected) in [
+            ("", ""),
+            ("61", "2g"),
+            ("626262", "a3gV"),
+            ("636363", "aPEr"),
+            (
+                "73696d706c792061206c6f6e6720737472696e67",
+                "2cFupjhnEsSn59qHXstmK2ffpLv2",
+            ),
+            (
+                "00eb15231dfceb60925886b67d065299925915aeb172c06647",
+                "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+            ),
+            ("516b6fcd0f", "ABnLTmg"),
+            ("bf4f89001e670274dd", "3SEo3LWLoPntC"),
+            ("572e4794", "3EFU7m"),
+            ("ecac89cad93923c02321", "EJDM8drfXA6uyA"),
+            ("10c8511e", "Rt5zm"),
+            ("00000000000000000000", "1111111111"),
+        ] {
+            assert_eq!(show(&unhex(input), HashFormat::Base58, false), expected);
+        }
+        assert_eq!(
+            show(&[0xff; 32], HashFormat::Base58, false),
+            "JEKNVnkbo3jma5nREBBJCDoXFVeKkD56V3xKrvRmWxFG"
+        );
+        assert_eq!(
+            show(&[0xff; MAX_HASH_LEN], HashFormat::Base58, false).len(),
+            88
+        );
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(show(&unhex("626262"), HashFormat::Base58, true), "a3gV");
+        assert_eq!(show(b"foobar", HashFormat::Base64Url, true), "Zm9v YmFy");
+        assert_eq!(show(b"fooba", HashFormat::Base64Url, true), "Zm9v YmE");
+        assert_eq!(
+            show(&[0xff; 32], HashFormat::Base58, true),
+            "JEKN Vnkb o3jm a5nR EBBJ CDoX FVeK kD56 V3xK rvRm WxFG"
+        );
+    }
+
+    #[test]
+    fn rejects_long_input() {
+        let mut s = String::new();
+        for format in [HashFormat::Hex, HashFormat::Base64Url, HashFormat::Base58] {
+            assert!(write_hash(&mut s, &[0; MAX_HASH_LEN + 1], format, false).is_err());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! The Stax and Flex home screen and its settings pages.
+//!
+//! The SDK's `NbglHomeAndSettings` only has a page of on/off switches, one per settings byte, so
+//! the home screen is set up here with the NBGL use case directly: one page of switches and
+//! radio lists for blind signing and the hash format.
+
+use crate::hash_format::HashFormat;
+use crate::settings::*;
+
+use core::ffi::{c_char, c_int};
+use core::mem::MaybeUninit;
+use include_gif::include_gif;
+use ledger_device_sdk::exit_app;
+use ledger_device_sdk::nbgl::NbglGlyph;
+use ledger_secure_sdk_sys::*;
+
+pub const APP_ICON_GLYPH: NbglGlyph = NbglGlyph::from_include(include_gif!("crab_64x64.gif", NBGL));
+
+/// A setting shown as a switch. Strings are NUL terminated, for NBGL.
+struct Switch {
+    text: &'static [u8],
+    sub_text: &'static [u8],
+    get: fn(&Settings) -> bool,
+    set: fn(&mut Settings, bool),
+}
+
+const SWITCHES: [Switch; 3] = [
+    Switch {
+        text: b"Testnet\0",
+        sub_text: b"Sign for the test network instead of mainnet\0",
+        get: |s| s.network() == Network::Testnet,
+        set: |s, on| {
+            s.set_network(if on {
+                Network::Testnet
+            } else {
+                Network::Mainnet
+            })
+        },
+    },
+    Switch {
+        text: b"Expert mode\0",
+        sub_text: b"Show fees, nonces and hashes when reviewing\0",
+        get: |s| s.expert_mode(),
+        set: |s, on| s.set_expert_mode(on),
+    },
+    Switch {
+        text: b"Group hashes\0",
+        sub_text: b"Show hashes in blocks of four characters\0",
+        get: |s| s.group_hashes(),
+        set: |s, on| s.set_group_hashes(on),
+    },
+];
+
+const BLIND_SIGNING_MODES: [BlindSigning; 4] = [
+    BlindSigning::Disabled,
+    BlindSigning::OneShot,
+    BlindSigning::Session,
+    BlindSigning::Always,
+];
+// In the order of BLIND_SIGNING_MODES.
+const BLIND_SIGNING_NAMES: [&[u8]; 4] = [
+    b"Blind signing off\0",
+    b"Blind sign once\0",
+    b"Blind sign this session\0",
+    b"Blind sign always\0",
+];
+
+const HASH_FORMATS: [HashFormat; 3] = [HashFormat::Base64Url, HashFormat::Hex, HashFormat::Base58];
+// In the order of HASH_FORMATS.
+const HASH_FORMAT_NAMES: [&[u8]; 3] = [b"Base64url hashes\0", b"Hex hashes\0", b"Base58 hashes\0"];
+
+// Switches take the tokens from FIRST_USER_TOKEN on, in order, and the radio lists the next ones.
+const BLIND_SIGNING_TOKEN: u8 = FIRST_USER_TOKEN as u8 + SWITCHES.len() as u8;
+const HASH_FORMAT_TOKEN: u8 = BLIND_SIGNING_TOKEN + 1;
+
+const APP_NAME: &[u8] = b"Alamgu Example\0";
+const INFO_TYPES: [&[u8]; 2] = [b"Version\0", b"Developer\0"];
+const INFO_CONTENTS: [&[u8]; 2] = [
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_bytes(),
+    concat!(env!("CARGO_PKG_AUTHORS"), "\0").as_bytes(),
+];
+
+// NBGL keeps pointers to these while the home screen is up, and reads the initial state of the
+// switches and of the radio lists from them each time it draws a settings page.
+static mut ICON: MaybeUninit<nbgl_icon_details_t> = MaybeUninit::uninit();
+static mut SWITCH_LIST: MaybeUninit<[nbgl_contentSwitch_t; SWITCHES.len()]> = MaybeUninit::uninit();
+static mut BLIND_SIGNING_NAME_PTRS: [*const c_char; BLIND_SIGNING_MODES.len()] =
+    [core::ptr::null(); BLIND_SIGNING_MODES.len()];
+static mut HASH_FORMAT_NAME_PTRS: [*const c_char; HASH_FORMATS.len()] =
+    [core::ptr::null(); HASH_FORMATS.len()];
+static mut CONTENTS: MaybeUninit<[nbgl_content_t; 3]> = MaybeUninit::uninit();
+static mut GENERIC_CONTENTS: MaybeUninit<nbgl_genericContents_t> = MaybeUninit::uninit();
+static mut INFO_TYPE_PTRS: [*const c_char; 2] = [core::ptr::null(); 2];
+static mut INFO_CONTENT_PTRS: [*const c_char; 2] = [core::ptr::null(); 2];
+static mut INFO_LIST: MaybeUninit<nbgl_contentInfoList_t> = MaybeUninit::uninit();
+
+/// Shows the home screen, whose settings pages then change the settings by themselves. Returns
+/// straight away; NBGL handles the touches as events come in.
+pub fn show_home(settings: &Settings) {
+    refresh(settings);
+    unsafe {
+        ICON.write((&APP_ICON_GLYPH).into());
+        for (ptr, info) in INFO_TYPE_PTRS.iter_mut().zip(INFO_TYPES) {
+            *ptr = info.as_ptr() as *const c_char;
+        }
+        for (ptr, info) in INFO_CONTENT_PTRS.iter_mut().zip(INFO_CONTENTS) {
+            *ptr = info.as_ptr() as *const c_char;
+        }
+        INFO_LIST.write(nbgl_contentInfoList_t {
+            infoTypes: INFO_TYPE_PTRS.as_ptr(),
+            infoContents: INFO_CONTENT_PTRS.as_ptr(),
+            nbInfos: INFO_TYPES.len() as u8,
+        });
+        nbgl_useCaseHomeAndSettings(
+            APP_NAME.as_ptr() as *const c_char,
+            ICON.as_ptr(),
+            core::ptr::null(),
+            INIT_HOME_PAGE as u8,
+            GENERIC_CONTENTS.as_ptr(),
+            INFO_LIST.as_ptr(),
+            core::ptr::null(),
+            Some(quit_callback),
+        );
+    }
+}
+
+/// Rebuilds the settings pages from the current settings.
+fn refresh(settings: &Settings) {
+    unsafe {
+        SWITCH_LIST.write(core::array::from_fn(|i| nbgl_contentSwitch_t {
+            text: SWITCHES[i].text.as_ptr() as *const c_char,
+            subText: SWITCHES[i].sub_text.as_ptr() as *const c_char,
+            initState: (SWITCHES[i].get)(settings) as nbgl_state_t,
+            token: FIRST_USER_TOKEN as u8 + i as u8,
+            tuneId: TUNE_TAP_CASUAL as u8,
+        }));
+        for (ptr, name) in BLIND_SIGNING_NAME_PTRS.iter_mut().zip(BLIND_SIGNING_NAMES) {
+            *ptr = name.as_ptr() as *const c_char;
+        }
+        let blind_signing = BLIND_SIGNING_MODES
+            .iter()
+            .position(|m| *m == settings.blind_signing())
+            .unwrap_or(0);
+        for (ptr, name) in HASH_FORMAT_NAME_PTRS.iter_mut().zip(HASH_FORMAT_NAMES) {
+            *ptr = name.as_ptr() as *const c_char;
+        }
+        let hash_format = HASH_FORMATS
+            .iter()
+            .position(|f| *f == settings.hash_format())
+            .unwrap_or(0);
+        CONTENTS.write([
+            nbgl_content_t {
+                content: nbgl_content_u {
+                    switchesList: nbgl_pageSwitchesList_s {
+                        switches: SWITCH_LIST.as_ptr() as *const nbgl_contentSwitch_t,
+                        nbSwitches: SWITCHES.len() as u8,
+                    },
+                },
+                contentActionCallback: Some(action_callback),
+                type_: SWITCHES_LIST,
+            },
+            nbgl_content_t {
+                content: nbgl_content_u {
+                    choicesList: nbgl_contentRadioChoice_t {
+                        __bindgen_anon_1: nbgl_contentRadioChoice_t__bindgen_ty_1 {
+                            names: BLIND_SIGNING_NAME_PTRS.as_ptr(),
+                        },
+                        localized: false,
+                        nbChoices: BLIND_SIGNING_MODES.len() as u8,
+                        initChoice: blind_signing as u8,
+                        token: BLIND_SIGNING_TOKEN,
+                        tuneId: TUNE_TAP_CASUAL as u8,
+                    },
+                },
+                contentActionCallback: Some(action_callback),
+                type_: CHOICES_LIST,
+            },
+            nbgl_content_t {
+                content: nbgl_content_u {
+                    choicesList: nbgl_contentRadioChoice_t {
+                        __bindgen_anon_1: nbgl_contentRadioChoice_t__bindgen_ty_1 {
+                            names: HASH_FORMAT_NAME_PTRS.as_ptr(),
+                        },
+                        localized: false,
+                        nbChoices: HASH_FORMATS.len() as u8,
+                        initChoice: hash_format as u8,
+                        token: HASH_FORMAT_TOKEN,
+                        tuneId: TUNE_TAP_CASUAL as u8,
+                    },
+                },
+                contentActionCallback: Some(action_callback),
+                type_: CHOICES_LIST,
+            },
+        ]);
+        GENERIC_CONTENTS.write(nbgl_genericContents_t {
+            callbackCallNeeded: false,
+            __bindgen_anon_1: nbgl_genericContents_t__bindgen_ty_1 {
+                contentsList: CONTENTS.as_ptr() as *const nbgl_content_t,
+            },
+            nbContents: 3,
+        });
+    }
+}
+
+/// Called by NBGL when a switch is toggled, with its token, or when a choice is selected in a
+/// radio list, with the list's token and the index of the choice.
+unsafe extern "C" fn action_callback(token: c_int, index: u8, _page: c_int) {
+    let mut settings = Settings;
+    if token == BLIND_SIGNING_TOKEN as c_int {
+        if let Some(mode) = BLIND_SIGNING_MODES.get(index as usize) {
+            settings.set_blind_signing(*mode);
+        }
+    } else if token == HASH_FORMAT_TOKEN as c_int {
+        if let Some(format) = HASH_FORMATS.get(index as usize) {
+            settings.set_hash_format(*format);
+        }
+    } else if let Some(switch) = SWITCHES.get((token - FIRST_USER_TOKEN as c_int) as usize) {
+        // Toggle from the stored setting rather than trust the switch's state.
+        (switch.set)(&mut settings, !(switch.get)(&settings));
+    }
+    // A one-shot blind signing mode may have been used up since the pages were drawn.
+    refresh(&settings);
+}
+
+unsafe extern "C" fn quit_callback() {
+    exit_app(0);
+}
@@ -1,70 +1,176 @@
+use crate::amount::Amount;
+use crate::assets::*;
+use crate::derivation::{
+    sign, tagged_hash, with_extended_key, with_public_key, with_x25519_scalar,
+};
+use crate::hash_format::write_hash;
 use crate::interface::*;
 use crate::settings::*;
+#[cfg(feature = "debug_instructions")]
 use crate::test_parsers::*;
 use crate::utils::*;
+use crate::x25519::{x25519, BASE_POINT};
+use crate::xchacha20poly1305::{self, NONCE_LEN, TAG_LEN};
 use alamgu_async_block::*;
-use arrayvec::ArrayVec;
+use arrayvec::{ArrayString, ArrayVec};
 use core::fmt::Write;
-use ledger_crypto_helpers::common::{try_option, Address, CryptographyError};
-use ledger_crypto_helpers::eddsa::{
-    ed25519_public_key_bytes, eddsa_sign, with_public_keys, Ed25519RawPubKeyAddress,
-};
+use ledger_crypto_helpers::common::{try_option, CryptographyError};
 use ledger_crypto_helpers::hasher::{Base64Hash, Blake2b, Hasher};
 use ledger_device_sdk::io::{StatusWords, SyscallError};
 use ledger_log::trace;
 use ledger_parser_combinators::async_parser::*;
 use ledger_parser_combinators::core_parsers::*;
 use ledger_parser_combinators::interp::*;
-use ledger_prompts_ui::final_accept_prompt;
+use ledger_secure_sdk_sys::cx_hash_sha256;
 
 use core::convert::TryFrom;
 use core::future::Future;
 use core::ops::Deref;
 use zeroize::Zeroizing;
 
-#[allow(clippy::upper_case_acronyms)]
-type PKH = Ed25519RawPubKeyAddress;
+include!(concat!(env!("OUT_DIR"), "/max_tx_size.rs"));
 
 pub type BipParserImplT =
     impl AsyncParser<Bip32Key, ByteStream> + HasOutput<Bip32Key, Output = ArrayVec<u32, 10>>;
 pub const BIP_PATH_PARSER: BipParserImplT = SubInterp(DefaultInterp);
 
+pub type BipBigEndianParserImplT = impl AsyncParser<Bip32KeyBigEndian, ByteStream>
+    + HasOutput<Bip32KeyBigEndian, Output = ArrayVec<u32, 10>>;
+pub const BIP_PATH_BIG_ENDIAN_PARSER: BipBigEndianParserImplT = SubInterp(DefaultInterp);
+
+pub type AccountIndexParserImplT =
+    impl AsyncParser<AccountIndex, ByteStream> + HasOutput<AccountIndex, Output = u32>;
+pub const ACCOUNT_INDEX_PARSER: AccountIndexParserImplT = DefaultInterp;
+
 // Need a path of length 5, as make_bip32_path panics with smaller paths
 pub const BIP32_PREFIX: [u32; 5] =
     ledger_device_sdk::ecc::make_bip32_path(b"m/44'/535348'/123'/0'/0'");
 
-pub async fn get_address_apdu(io: HostIO, prompt: bool) {
+/// The account in BIP32_PREFIX, which an account index replaces.
+const ACCOUNT_STEP: usize = 2;
+
+const HARDENED: u32 = 0x8000_0000;
+
+/// Reads one derivation path in `encoding` from the start of `input`. An account index is
+/// expanded to BIP32_PREFIX with that (hardened) account.
+async fn read_path(encoding: PathEncoding, input: &mut ByteStream) -> ArrayVec<u32, 10> {
+    match encoding {
+        PathEncoding::LittleEndian => BIP_PATH_PARSER.parse(input).await,
+        PathEncoding::BigEndian => BIP_PATH_BIG_ENDIAN_PARSER.parse(input).await,
+        PathEncoding::AccountIndex => {
+            let account = ACCOUNT_INDEX_PARSER.parse(input).await;
+            if account & HARDENED != 0 {
+                reject::<()>(SyscallError::InvalidParameter as u16).await;
+            }
+            let mut path: ArrayVec<u32, 10> = BIP32_PREFIX.iter().copied().collect();
+            path[ACCOUNT_STEP] = HARDENED | account;
+            path
+        }
+    }
+}
+
+/// Reads a whole derivation path parameter in `encoding`.
+async fn parse_path(encoding: PathEncoding, input: &mut ByteStream) -> ArrayVec<u32, 10> {
+    let path = read_path(encoding, input).await;
+    expect_end(input).await;
+    path
+}
+
+/// Whether `path` is one of the app's, and one `scheme` can derive: SLIP-10 has no
+/// non-hardened ed25519 steps.
+fn is_app_path(path: &[u32], scheme: DerivationScheme) -> bool {
+    path.starts_with(&BIP32_PREFIX[0..2])
+        && (scheme != DerivationScheme::Slip10 || path.iter().all(|step| step & HARDENED != 0))
+}
+
+/// Reads the SignPaths of a signature request, refusing a path given twice.
+async fn parse_sign_paths(
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    input: &mut ByteStream,
+) -> ArrayVec<ArrayVec<u32, 10>, MAX_SIGN_INPUTS> {
+    let mut paths = ArrayVec::new();
+    loop {
+        let path = read_path(encoding, input).await;
+        if !is_app_path(&path, scheme) || paths.contains(&path) || paths.try_push(path).is_err() {
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+        if input.is_empty().await {
+            return paths;
+        }
+    }
+}
+
+/// The title of each signing address in a review: `title` alone for a single input, and
+/// numbered for several.
+fn input_titles(title: &str, inputs: usize) -> ArrayVec<ArrayString<24>, MAX_SIGN_INPUTS> {
+    (1..=inputs)
+        .map(|i| {
+            let mut numbered = ArrayString::new();
+            let _ = if inputs == 1 {
+                write!(numbered, "{title}")
+            } else {
+                write!(numbered, "{title} {i}")
+            };
+            numbered
+        })
+        .collect()
+}
+
+/// Writes an address with the prefix of `network`.
+fn write_address(w: &mut dyn Write, network: Network, address: &[u8]) -> Result<(), ScrollerError> {
+    write!(w, "{}:", network.address_prefix())?;
+    write_hex(w, address)
+}
+
+/// Every review starts with the network banner, if there is one.
+fn network_review<'a>(network: Network) -> Review<'a> {
+    match network.banner() {
+        Some(banner) => Review::new().banner(banner),
+        None => Review::new(),
+    }
+}
+
+pub async fn get_address_apdu(
+    io: HostIO,
+    prompt: bool,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
     let input = match io.get_params::<1>() {
         Some(v) => v,
         None => reject(SyscallError::InvalidParameter as u16).await,
     };
 
-    let path = BIP_PATH_PARSER.parse(&mut input[0].clone()).await;
+    let path = parse_path(encoding, &mut input[0].clone()).await;
 
-    if !path.starts_with(&BIP32_PREFIX[0..2]) {
+    if !is_app_path(&path, scheme) {
         reject::<()>(SyscallError::InvalidParameter as u16).await;
     }
 
     let mut rv = ArrayVec::<u8, 220>::new();
 
-    if with_public_keys(&path, false, |key, pkh: &PKH| {
+    let mut reviewed = Ok(());
+    if with_public_key(&path, scheme, |key| {
         try_option(|| -> Option<()> {
             if prompt {
-                scroller("Provide Public Key", |_w| Ok(()))?;
-                scroller_paginated("Address", |w| Ok(write!(w, "{pkh}")?))?;
-                final_accept_prompt(&[])?;
+                reviewed = network_review(settings.network())
+                    .intro("Provide Public Key")
+                    .field("Address", &|w| write_address(w, settings.network(), key))
+                    .show();
+                reviewed.ok()?;
             }
             // Should return the format that the chain customarily uses for public keys; for
-            // ed25519 that's usually r | s with no prefix, which isn't quite our internal
-            // representation.
-            let key_bytes = ed25519_public_key_bytes(key);
+            // ed25519 that's the 32 byte encoding of the point, with no prefix.
+            let key_bytes = &key[..];
 
             rv.try_push(u8::try_from(key_bytes.len()).ok()?).ok()?;
             rv.try_extend_from_slice(key_bytes).ok()?;
 
             // And we'll send the address along; in our case it happens to be the same as the
             // public key, but in general it's something computed from the public key.
-            let binary_address = pkh.get_binary_address();
+            let binary_address = &key[..];
             rv.try_push(u8::try_from(binary_address.len()).ok()?).ok()?;
             rv.try_extend_from_slice(binary_address).ok()?;
             Some(())
@@ -72,45 +178,292 @@ pub async fn get_address_apdu(io: HostIO, prompt: bool) {
     })
     .is_err()
     {
-        reject::<()>(StatusWords::UserCancelled as u16).await;
+        reject::<()>(refusal(reviewed)).await;
+    }
+
+    io.result_final(&rv).await;
+}
+
+/// Exports the public key and chain code of an account, from which watch-only wallets derive
+/// the account's non-hardened children. Only BIP32-Ed25519 keys are exported: the legacy and
+/// SLIP-10 schemes have no public derivation, and BIP340 keys are deliberately unsupported.
+pub async fn get_extended_pubkey_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
+    let input = match io.get_params::<1>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let mut path = parse_path(encoding, &mut input[0].clone()).await;
+    if encoding == PathEncoding::AccountIndex {
+        path.truncate(ACCOUNT_STEP + 1);
+    }
+
+    if scheme != DerivationScheme::Bip32Ed25519 {
+        reject::<()>(SyscallError::NotSupported as u16).await;
+    }
+    if path.len() != ACCOUNT_STEP + 1
+        || !path.starts_with(&BIP32_PREFIX[0..2])
+        || path[ACCOUNT_STEP] & HARDENED == 0
+    {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+    let account = path[ACCOUNT_STEP] & !HARDENED;
+
+    let mut rv = ArrayVec::<u8, 66>::new();
+    let mut reviewed = Ok(());
+    if with_extended_key(&path, |key| {
+        reviewed = network_review(settings.network())
+            .field("Extended Key", &|w| {
+                Ok(write!(
+                    w,
+                    "Export extended public key for account {account}?"
+                )?)
+            })
+            .action("Export Key?")
+            .show();
+        reviewed.map_err(|_| CryptographyError::NoneError)?;
+        for part in [&key.public, &key.chain_code] {
+            let _ = rv.try_push(part.len() as u8);
+            let _ = rv.try_extend_from_slice(part);
+        }
+        Ok(())
+    })
+    .is_err()
+    {
+        reject::<()>(refusal(reviewed)).await;
     }
 
     io.result_final(&rv).await;
 }
 
+pub struct TransferDetails {
+    pub chain_id: u32,
+    pub asset_id: [u8; 32],
+    pub recipient: [u8; 32],
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    pub expiry: u64,
+}
+
+pub type TransferParserImplT =
+    impl AsyncParser<Transfer, ByteStream> + HasOutput<Transfer, Output = TransferDetails>;
+pub const TRANSFER_PARSER: TransferParserImplT = Action(
+    DefaultInterp,
+    |(_, (chain_id, (asset_id, (recipient, (amount, (fee, (nonce, expiry))))))): (
+        u8,
+        (u32, ([u8; 32], ([u8; 32], (u64, (u64, (u64, u64)))))),
+    )| {
+        Some(TransferDetails {
+            chain_id,
+            asset_id,
+            recipient,
+            amount,
+            fee,
+            nonce,
+            expiry,
+        })
+    },
+);
+
 const fn hasher_parser(
 ) -> impl LengthDelimitedParser<Byte, ByteStream> + HasOutput<Byte, Output = (Blake2b, Option<()>)>
 {
     ObserveBytes(Hasher::new, Hasher::update, DropInterp)
 }
 
-pub async fn sign_apdu(io: HostIO, settings: Settings) {
-    let mut input = match io.get_params::<2>() {
-        Some(v) => v,
-        None => reject(SyscallError::InvalidParameter as u16).await,
-    };
+/// Blind signed payloads up to this size are shown as hex, as are all of them in expert mode.
+const RAW_REVIEW_MAX_LEN: usize = 64;
 
-    let length = usize::from_le_bytes(input[0].read().await);
-    let mut txn = input[0].clone();
+/// Bytes of a raw payload shown per screen.
+const RAW_PAGE_LEN: usize = 32;
 
-    let hash: Zeroizing<Base64Hash<32>> =
-        hasher_parser().parse(&mut txn, length).await.0.finalize();
+/// Hashes a payload like `hasher_parser`, and shows it as hex one page at a time as the bytes
+/// stream past. Pages can't be revisited, since the bytes are gone once they are hashed.
+struct PayloadReview {
+    hasher: Blake2b,
+    page: ArrayVec<u8, RAW_PAGE_LEN>,
+    offset: usize,
+    rejected: bool,
+}
 
-    let path = BIP_PATH_PARSER.parse(&mut input[1].clone()).await;
+impl PayloadReview {
+    fn new() -> Self {
+        PayloadReview {
+            hasher: Hasher::new(),
+            page: ArrayVec::new(),
+            offset: 0,
+            rejected: false,
+        }
+    }
 
-    if !path.starts_with(&BIP32_PREFIX[0..2]) {
-        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        for byte in bytes {
+            self.page.push(*byte);
+            if self.page.is_full() {
+                self.show_page();
+            }
+        }
+    }
+
+    // After a rejection the rest of the payload still has to be read, but is no longer shown.
+    fn show_page(&mut self) {
+        if !self.rejected && !self.page.is_empty() {
+            let mut title = ArrayString::<24>::new();
+            let _ = write!(
+                title,
+                "Bytes {}-{}",
+                self.offset + 1,
+                self.offset + self.page.len()
+            );
+            let page = &self.page;
+            self.rejected = scroller_paginated(&title, |w| write_hex(w, page)).is_none();
+        }
+        self.offset += self.page.len();
+        self.page.clear();
     }
 
-    // The example app doesn't have a parser; every transaction is rejected
-    // unless we are blind signing.
-    let known_txn = false;
+    /// The hash, unless the user rejected one of the pages.
+    fn finish(mut self) -> Option<Zeroizing<Base64Hash<32>>> {
+        self.show_page();
+        if self.rejected {
+            None
+        } else {
+            Some(self.hasher.finalize())
+        }
+    }
+}
+
+const fn payload_review_parser(
+) -> impl LengthDelimitedParser<Byte, ByteStream> + HasOutput<Byte, Output = (PayloadReview, Option<()>)>
+{
+    ObserveBytes(PayloadReview::new, PayloadReview::update, DropInterp)
+}
+
+/// BIP340 signatures of transactions are over this tagged hash of the transaction hash, as
+/// BIP340 recommends, so that they can never be valid for another protocol's messages.
+const TRANSACTION_TAG: &[u8] = b"alamgu-example/transaction";
 
-    if known_txn {
-        if final_accept_prompt(&["Sign Transaction?"]).is_none() {
+/// What is signed for a transaction: its hash, or for BIP340 the tagged hash of that.
+fn transaction_message(scheme: DerivationScheme, hash: &Base64Hash<32>) -> [u8; 32] {
+    if scheme == DerivationScheme::Bip340 {
+        tagged_hash(TRANSACTION_TAG, &hash.0)
+    } else {
+        hash.0
+    }
+}
+
+/// Refuses a transaction for another chain than the one the app is set to, with a warning.
+async fn check_chain(network: Network, chain_id: u32) {
+    if chain_id != network.chain_id() {
+        scroller("WARNING", |w| {
+            Ok(write!(
+                w,
+                "Transaction is for chain {chain_id}, but the app is set to {}",
+                network.name()
+            )?)
+        });
+        reject::<()>(SyscallError::InvalidState as u16).await;
+    }
+}
+
+/// A transaction read from a signature request: its size, its hash, and its details if it is a
+/// transfer.
+struct Transaction {
+    length: usize,
+    transfer: Option<TransferDetails>,
+    hash: Zeroizing<Base64Hash<32>>,
+    /// Whether it was shown as hex while it was read, after the blind signing warning.
+    payload_shown: bool,
+}
+
+/// The warning every blind signature is reviewed with.
+const UNRECOGNIZED_WARNING: &str = "Transaction not recognized";
+
+/// Reads a transaction's size and the transaction from `input`, hashing it. One that can only
+/// be blind signed is shown as hex while it is read, if it is short or the app is in expert mode.
+async fn read_transaction(settings: &Settings, input: &mut ByteStream) -> Transaction {
+    let length = usize::from_le_bytes(input.read().await);
+
+    if length > MAX_TX_SIZE {
+        scroller("WARNING", |w| {
+            Ok(write!(
+                w,
+                "Transaction is {length} bytes, more than the {MAX_TX_SIZE} the app can sign"
+            )?)
+        });
+        reject::<()>(AppStatusWords::TransactionTooLarge as u16).await;
+    }
+
+    // Transfers are recognized by their length and tag, and shown in full; anything else can
+    // only be blind signed.
+    let transfer = if length == TRANSFER_LENGTH {
+        let [tag] = input.clone().read().await;
+        if tag == TRANSFER_TAG {
+            Some(TRANSFER_PARSER.parse(&mut input.clone()).await)
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let show_payload = transfer.is_none()
+        && settings.blind_signing().enabled()
+        && (settings.expert_mode() || length <= RAW_REVIEW_MAX_LEN);
+    let hash: Zeroizing<Base64Hash<32>> = if show_payload {
+        // The hex pages go by as the payload is read, before the review, so its warning comes
+        // first.
+        if scroller("WARNING", |w| Ok(w.write_str(UNRECOGNIZED_WARNING)?)).is_none() {
             reject::<()>(StatusWords::UserCancelled as u16).await;
-        };
-    } else if settings.get() == 0 {
+        }
+        match payload_review_parser()
+            .parse(input, length)
+            .await
+            .0
+            .finish()
+        {
+            Some(hash) => hash,
+            None => reject(StatusWords::UserCancelled as u16).await,
+        }
+    } else {
+        hasher_parser().parse(input, length).await.0.finalize()
+    };
+
+    Transaction {
+        length,
+        transfer,
+        hash,
+        payload_shown: show_payload,
+    }
+}
+
+/// Shows a transaction signed by the keys `from` for approval, and rejects the request unless
+/// the user approves it. `to` titles a transfer's recipient.
+async fn review_transaction(settings: &Settings, txn: &Transaction, from: &[[u8; 32]], to: &str) {
+    let network = settings.network();
+    let hash = &txn.hash;
+
+    if let Some(transfer) = &txn.transfer {
+        check_chain(network, transfer.chain_id).await;
+        if let Err(e) = show_transfer(
+            settings,
+            transfer,
+            hash,
+            from,
+            "Review Transfer",
+            to,
+            "Sign Transaction?",
+        ) {
+            reject::<()>(e.status_word()).await;
+        }
+    } else if !settings.blind_signing().enabled() {
         scroller("WARNING", |w| {
             Ok(write!(
                 w,
@@ -119,41 +472,764 @@ pub async fn sign_apdu(io: HostIO, settings: Settings) {
         });
         reject::<()>(SyscallError::NotSupported as u16).await;
     } else {
-        if scroller("WARNING", |w| Ok(write!(w, "Transaction not recognized")?)).is_none() {
-            reject::<()>(StatusWords::UserCancelled as u16).await;
+        let length = txn.length;
+        let titles = input_titles("Sign for Address", from.len());
+        let addresses: ArrayVec<_, MAX_SIGN_INPUTS> = from
+            .iter()
+            .map(|key| move |w: &mut dyn Write| write_address(w, network, key))
+            .collect();
+        let size_value = |w: &mut dyn Write| Ok(write!(w, "{length} bytes")?);
+        let hash_value = |w: &mut dyn Write| {
+            Ok(write_hash(
+                w,
+                &hash.deref().0,
+                settings.hash_format(),
+                settings.group_hashes(),
+            )?)
+        };
+        let mut review = network_review(network);
+        if !txn.payload_shown {
+            review = review.warning(UNRECOGNIZED_WARNING);
+        }
+        review = review
+            .field("Payload size", &size_value)
+            .field("Transaction hash", &hash_value);
+        for (title, address) in titles.iter().zip(addresses.iter()) {
+            review = review.field(title, address);
+        }
+        if let Err(e) = review.action("Blind Sign Transaction?").show() {
+            reject::<()>(e.status_word()).await;
         }
+    }
+}
 
-        if scroller("Transaction hash", |w| Ok(write!(w, "{}", hash.deref())?)).is_none() {
-            reject::<()>(StatusWords::UserCancelled as u16).await;
+pub async fn sign_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    mut settings: Settings,
+) {
+    let mut input = match io.get_params::<2>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let txn = read_transaction(&settings, &mut input[0]).await;
+    expect_end(&mut input[0]).await;
+
+    let paths = parse_sign_paths(encoding, scheme, &mut input[1].clone()).await;
+    let mut keys = ArrayVec::<[u8; 32], MAX_SIGN_INPUTS>::new();
+    for path in paths.iter() {
+        match with_public_key(path, scheme, |key| Ok(*key)) {
+            Ok(key) => keys.push(key),
+            Err(_) => reject::<()>(SyscallError::InvalidParameter as u16).await,
         }
+    }
 
-        if with_public_keys(&path, false, |_, pkh: &PKH| {
-            scroller("Sign for Address", |w| Ok(write!(w, "{pkh}")?))
-                .ok_or(CryptographyError::NoneError)
-        })
-        .is_err()
-        {
-            reject::<()>(StatusWords::UserCancelled as u16).await;
+    review_transaction(&settings, &txn, &keys, "To").await;
+
+    // By the time we get here, we've approved and just need to do the signature.
+    let message = transaction_message(scheme, &txn.hash);
+    let mut sigs = ArrayVec::<[u8; 64], MAX_SIGN_INPUTS>::new();
+    for path in paths.iter() {
+        match sign(path, scheme, &message) {
+            Ok(sig) => sigs.push(sig),
+            Err(_) => reject::<()>(SyscallError::Unspecified as u16).await,
         }
+    }
+    if txn.transfer.is_none() {
+        settings.blind_signature_done();
+    }
+    // One signature per input, in the order of the paths.
+    let (last, rest) = sigs.split_last().unwrap();
+    for sig in rest {
+        io.result_accumulating(&sig[..]).await;
+    }
+    io.result_final(&last[..]).await;
+}
 
-        if final_accept_prompt(&["Blind Sign Transaction?"]).is_none() {
-            reject::<()>(StatusWords::UserCancelled as u16).await;
+/// Writes an amount of `asset`. Without a descriptor it can only be shown in base units, so
+/// reviews show the asset id next to it.
+fn write_amount(
+    w: &mut dyn Write,
+    asset: &Option<AssetInfo>,
+    amount: impl Into<u128>,
+) -> Result<(), ScrollerError> {
+    match asset {
+        Some(asset) => write!(
+            w,
+            "{}",
+            Amount::new(amount, asset.decimals).ticker(&asset.ticker)
+        )?,
+        None => write!(w, "{}", Amount::new(amount, 0))?,
+    }
+    Ok(())
+}
+
+/// Recipient, amount and the address of each input, plus fee, nonce, expiry, chain id and hash
+/// in expert mode.
+fn show_transfer(
+    settings: &Settings,
+    transfer: &TransferDetails,
+    hash: &Base64Hash<32>,
+    from: &[[u8; 32]],
+    intro: &str,
+    to: &str,
+    action: &str,
+) -> Result<(), ReviewError> {
+    let network = settings.network();
+    let expert = settings.expert_mode();
+    let asset = asset_info(network.chain_id(), &transfer.asset_id);
+    let amount_value = |w: &mut dyn Write| write_amount(w, &asset, transfer.amount);
+    let asset_value = |w: &mut dyn Write| write_hex(w, &transfer.asset_id);
+    let recipient_value = |w: &mut dyn Write| write_address(w, network, &transfer.recipient);
+    let from_titles = input_titles("From", from.len());
+    let from_values: ArrayVec<_, MAX_SIGN_INPUTS> = from
+        .iter()
+        .map(|key| move |w: &mut dyn Write| write_address(w, network, key))
+        .collect();
+    let fee_value = |w: &mut dyn Write| -> Result<(), ScrollerError> {
+        let fee = Amount::new(transfer.fee, NATIVE_DECIMALS).ticker(NATIVE_TICKER);
+        Ok(write!(w, "{fee}")?)
+    };
+    let nonce_value = |w: &mut dyn Write| Ok(write!(w, "{}", transfer.nonce)?);
+    let expiry_value = |w: &mut dyn Write| Ok(write!(w, "Block {}", transfer.expiry)?);
+    let chain_value = |w: &mut dyn Write| Ok(write!(w, "{}", transfer.chain_id)?);
+    let hash_value = |w: &mut dyn Write| {
+        Ok(write_hash(
+            w,
+            &hash.0,
+            settings.hash_format(),
+            settings.group_hashes(),
+        )?)
+    };
+
+    let mut review = network_review(network)
+        .intro(intro)
+        .field("Amount", &amount_value);
+    if asset.is_none() || expert {
+        review = review.field("Asset", &asset_value);
+    }
+    review = review.field(to, &recipient_value);
+    for (title, value) in from_titles.iter().zip(from_values.iter()) {
+        review = review.field(title, value);
+    }
+    if expert {
+        review = review
+            .field("Fee", &fee_value)
+            .field("Nonce", &nonce_value)
+            .field("Expiry", &expiry_value)
+            .field("Chain ID", &chain_value)
+            .field("Transaction hash", &hash_value);
+    }
+    review.action(action).show()
+}
+
+/// Reads the length of the next transaction of a batch, refusing anything but a transfer, and
+/// leaves `batch` at its first byte.
+async fn read_batch_transfer(batch: &mut ByteStream) {
+    let length = usize::from_le_bytes(batch.read().await);
+    let [tag] = batch.clone().read().await;
+    if length != TRANSFER_LENGTH || tag != TRANSFER_TAG {
+        scroller("WARNING", |w| {
+            Ok(write!(w, "Batches can only hold transfers")?)
+        });
+        reject::<()>(SyscallError::NotSupported as u16).await;
+    }
+}
+
+pub async fn sign_batch_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
+    let input = match io.get_params::<2>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let path = parse_path(encoding, &mut input[1].clone()).await;
+    if !is_app_path(&path, scheme) {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+    let from = match with_public_key(&path, scheme, |key| Ok(*key)) {
+        Ok(key) => key,
+        Err(_) => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let network = settings.network();
+    let expert = settings.expert_mode();
+
+    // First pass: check every transfer, and add them up. Amounts are summed in a u128, which
+    // MAX_BATCH_LEN u64s can't overflow.
+    let mut batch = input[0].clone();
+    let [count] = batch.read().await;
+    let count = count as usize;
+    if count == 0 || count > MAX_BATCH_LEN {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+    let mut asset_id = [0; 32];
+    let mut total: u128 = 0;
+    let mut fees: u128 = 0;
+    let mut recipients = ArrayVec::<[u8; 32], MAX_BATCH_LEN>::new();
+    for i in 0..count {
+        read_batch_transfer(&mut batch).await;
+        let transfer = TRANSFER_PARSER.parse(&mut batch).await;
+        check_chain(network, transfer.chain_id).await;
+        if i == 0 {
+            asset_id = transfer.asset_id;
+        } else if transfer.asset_id != asset_id {
+            scroller("WARNING", |w| {
+                Ok(write!(w, "Transfers in a batch must all be of one asset")?)
+            });
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+        total += u128::from(transfer.amount);
+        fees += u128::from(transfer.fee);
+        if !recipients.contains(&transfer.recipient) {
+            recipients.push(transfer.recipient);
+        }
+    }
+    expect_end(&mut batch).await;
+
+    // Each transfer can be reviewed, and rejected, before the summary.
+    if choice("Review each?", "Yes", "No") {
+        let mut batch = input[0].clone();
+        let _: [u8; 1] = batch.read().await;
+        for i in 1..=count {
+            let _: [u8; 4] = batch.read().await;
+            let transfer = TRANSFER_PARSER.parse(&mut batch.clone()).await;
+            let hash = hasher_parser()
+                .parse(&mut batch, TRANSFER_LENGTH)
+                .await
+                .0
+                .finalize();
+            let mut intro = ArrayString::<24>::new();
+            let _ = write!(intro, "Transfer {i} of {count}");
+            if let Err(e) = show_transfer(
+                &settings,
+                &transfer,
+                &hash,
+                &[from],
+                &intro,
+                "To",
+                "Accept Transfer?",
+            ) {
+                reject::<()>(e.status_word()).await;
+            }
+        }
+    }
+
+    let asset = asset_info(network.chain_id(), &asset_id);
+    let count_value = |w: &mut dyn Write| Ok(write!(w, "{count}")?);
+    let total_value = |w: &mut dyn Write| write_amount(w, &asset, total);
+    let asset_value = |w: &mut dyn Write| write_hex(w, &asset_id);
+    let recipients_value = |w: &mut dyn Write| Ok(write!(w, "{}", recipients.len())?);
+    let from_value = |w: &mut dyn Write| write_address(w, network, &from);
+    let fees_value = |w: &mut dyn Write| -> Result<(), ScrollerError> {
+        let fees = Amount::new(fees, NATIVE_DECIMALS).ticker(NATIVE_TICKER);
+        Ok(write!(w, "{fees}")?)
+    };
+    let mut review = network_review(network)
+        .intro("Review Batch")
+        .field("Transfers", &count_value)
+        .field("Total Amount", &total_value);
+    if asset.is_none() || expert {
+        review = review.field("Asset", &asset_value);
+    }
+    review = review
+        .field("Recipients", &recipients_value)
+        .field("From", &from_value);
+    if expert {
+        review = review.field("Total Fees", &fees_value);
+    }
+    if let Err(e) = review.action("Sign Batch?").show() {
+        reject::<()>(e.status_word()).await;
+    }
+
+    // Approved: sign each transfer in turn, sending its signature as soon as it is made.
+    let mut batch = input[0].clone();
+    let _: [u8; 1] = batch.read().await;
+    for i in 1..=count {
+        let _: [u8; 4] = batch.read().await;
+        let hash = hasher_parser()
+            .parse(&mut batch, TRANSFER_LENGTH)
+            .await
+            .0
+            .finalize();
+        let sig = match sign(&path, scheme, &transaction_message(scheme, &hash)) {
+            Ok(sig) => sig,
+            Err(_) => reject(SyscallError::Unspecified as u16).await,
         };
+        if i < count {
+            io.result_accumulating(&sig[..]).await;
+        } else {
+            io.result_final(&sig[..]).await;
+        }
     }
+}
 
-    // By the time we get here, we've approved and just need to do the signature.
-    if let Some(sig) = { eddsa_sign(&path, false, &hash.deref().0).ok() } {
-        io.result_final(&sig.0[0..]).await;
+pub type ContainerInputParserImplT = impl AsyncParser<ContainerInput, ByteStream>
+    + HasOutput<ContainerInput, Output = ([u8; 32], (ArrayVec<u32, 10>, ArrayVec<u8, 64>))>;
+pub const CONTAINER_INPUT_PARSER: ContainerInputParserImplT = (
+    DefaultInterp,
+    (SubInterp(DefaultInterp), SubInterp(DefaultInterp)),
+);
+
+/// Whether `path` is one of the app's, and the device's key for it under `scheme` is `key`.
+fn is_own_key(path: &[u32], scheme: DerivationScheme, key: &[u8; 32]) -> bool {
+    is_app_path(path, scheme)
+        && matches!(
+            with_public_key(path, scheme, |own| Ok(own == key)),
+            Ok(true)
+        )
+}
+
+/// Bytes of a result sent at a time.
+const RESULT_CHUNK_LEN: usize = 128;
+
+/// Sends a result of any size in pieces, each but the last with `RESULT_ACCUMULATING`.
+struct ResultWriter {
+    io: HostIO,
+    chunk: ArrayVec<u8, RESULT_CHUNK_LEN>,
+}
+
+impl ResultWriter {
+    fn new(io: HostIO) -> Self {
+        ResultWriter {
+            io,
+            chunk: ArrayVec::new(),
+        }
+    }
+
+    async fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            if self.chunk.is_full() {
+                self.io.result_accumulating(&self.chunk).await;
+                self.chunk.clear();
+            }
+            self.chunk.push(*byte);
+        }
+    }
+
+    /// Copies the next `length` bytes of `input` to the result.
+    async fn copy(&mut self, input: &mut ByteStream, length: usize) {
+        for _ in 0..length {
+            let byte: [u8; 1] = input.read().await;
+            self.write(&byte).await;
+        }
+    }
+
+    /// Writes `path` as a Bip32Key.
+    async fn write_path(&mut self, path: &[u32]) {
+        self.write(&[path.len() as u8]).await;
+        for step in path {
+            self.write(&step.to_le_bytes()).await;
+        }
+    }
+
+    async fn finish(self) {
+        self.io.result_final(&self.chunk).await;
+    }
+}
+
+pub async fn sign_container_apdu(io: HostIO, scheme: DerivationScheme, mut settings: Settings) {
+    let input = match io.get_params::<1>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let mut container = input[0].clone();
+    let [version] = container.read().await;
+    if version != CONTAINER_VERSION {
+        reject::<()>(SyscallError::NotSupported as u16).await;
+    }
+    let txn = read_transaction(&settings, &mut container).await;
+
+    // The device signs the inputs that list a path, which must be for the key the input lists.
+    // The others are for other parties to sign.
+    let [input_count] = container.read().await;
+    let input_count = input_count as usize;
+    if input_count == 0 || input_count > MAX_CONTAINER_INPUTS {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+    let mut paths = ArrayVec::<ArrayVec<u32, 10>, MAX_SIGN_INPUTS>::new();
+    let mut keys = ArrayVec::<[u8; 32], MAX_SIGN_INPUTS>::new();
+    for i in 1..=input_count {
+        let (key, (path, signature)) = CONTAINER_INPUT_PARSER.parse(&mut container).await;
+        if !signature.is_empty() && !signature.is_full() {
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+        if path.is_empty() {
+            continue;
+        }
+        if !is_own_key(&path, scheme, &key) {
+            scroller("WARNING", |w| {
+                Ok(write!(w, "Input {i} is not for a key of this device")?)
+            });
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+        if paths.contains(&path) || paths.try_push(path).is_err() {
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+        keys.push(key);
+    }
+    if paths.is_empty() {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+
+    // Change hints can only be checked against the recipient of a transfer, which is then shown
+    // as the user's own.
+    let [hint_count] = container.read().await;
+    if hint_count as usize > MAX_CHANGE_HINTS {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+    for _ in 0..hint_count {
+        let path = BIP_PATH_PARSER.parse(&mut container).await;
+        let is_change = match &txn.transfer {
+            Some(transfer) => is_own_key(&path, scheme, &transfer.recipient),
+            None => false,
+        };
+        if !is_change {
+            scroller("WARNING", |w| {
+                Ok(write!(
+                    w,
+                    "Change hint does not match the transfer's recipient"
+                )?)
+            });
+            reject::<()>(SyscallError::InvalidParameter as u16).await;
+        }
+    }
+    expect_end(&mut container).await;
+
+    let to = if hint_count > 0 {
+        "To Own Account"
+    } else {
+        "To"
+    };
+    review_transaction(&settings, &txn, &keys, to).await;
+
+    let message = transaction_message(scheme, &txn.hash);
+    let mut sigs = ArrayVec::<[u8; 64], MAX_SIGN_INPUTS>::new();
+    for path in paths.iter() {
+        match sign(path, scheme, &message) {
+            Ok(sig) => sigs.push(sig),
+            Err(_) => reject::<()>(SyscallError::Unspecified as u16).await,
+        }
+    }
+    if txn.transfer.is_none() {
+        settings.blind_signature_done();
+    }
+
+    // The result is the container again, with the device's signatures in place of whatever its
+    // inputs held. It is parsed again as it is written out, as it is too big to keep.
+    let mut container = input[0].clone();
+    let mut out = ResultWriter::new(io);
+    let [version] = container.read().await;
+    let length: [u8; 4] = container.read().await;
+    out.write(&[version]).await;
+    out.write(&length).await;
+    out.copy(&mut container, txn.length).await;
+    let [input_count] = container.read().await;
+    out.write(&[input_count]).await;
+    let mut own_sigs = sigs.iter();
+    for _ in 0..input_count {
+        let (key, (path, signature)) = CONTAINER_INPUT_PARSER.parse(&mut container).await;
+        out.write(&key).await;
+        out.write_path(&path).await;
+        let signature = if path.is_empty() {
+            &signature[..]
+        } else {
+            match own_sigs.next() {
+                Some(sig) => &sig[..],
+                None => reject(SyscallError::Unspecified as u16).await,
+            }
+        };
+        out.write(&[signature.len() as u8]).await;
+        out.write(signature).await;
+    }
+    let [hint_count] = container.read().await;
+    out.write(&[hint_count]).await;
+    for _ in 0..hint_count {
+        let path = BIP_PATH_PARSER.parse(&mut container).await;
+        out.write_path(&path).await;
+    }
+    out.finish().await;
+}
+
+/// Prepended to the message of every ownership proof. Transaction signatures are over a 32 byte
+/// hash, so no proof can ever be mistaken for one.
+const OWNERSHIP_PROOF_DOMAIN: &[u8] = b"alamgu-example ownership proof";
+
+/// BIP340 keys sign this tagged hash of a proof's message, as the OS only signs 32 byte messages.
+const OWNERSHIP_PROOF_TAG: &[u8] = b"alamgu-example/ownership-proof";
+
+pub type OwnershipChallengeParserImplT = impl AsyncParser<OwnershipChallenge, ByteStream>
+    + HasOutput<OwnershipChallenge, Output = ([u8; 32], ArrayVec<u8, MAX_SERVICE_LEN>)>;
+pub const OWNERSHIP_CHALLENGE_PARSER: OwnershipChallengeParserImplT =
+    (DefaultInterp, SubInterp(DefaultInterp));
+
+pub async fn prove_ownership_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
+    let input = match io.get_params::<2>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let (nonce, service) = parse_exactly(&OWNERSHIP_CHALLENGE_PARSER, &mut input[0].clone()).await;
+    let path = parse_path(encoding, &mut input[1].clone()).await;
+
+    if !is_app_path(&path, scheme) {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+
+    // The service name is shown as-is, so only printable ASCII is allowed.
+    let service = match core::str::from_utf8(&service) {
+        Ok(s) if !s.is_empty() && s.bytes().all(|c| c == b' ' || c.is_ascii_graphic()) => s,
+        _ => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let network = settings.network();
+    let mut message = ArrayVec::<u8, 192>::new();
+
+    let mut reviewed = Ok(());
+    if with_public_key(&path, scheme, |address| {
+        // domain | address | service length | service | nonce
+        let _ = message.try_extend_from_slice(OWNERSHIP_PROOF_DOMAIN);
+        let _ = message.try_extend_from_slice(address);
+        let _ = message.try_push(service.len() as u8);
+        let _ = message.try_extend_from_slice(service.as_bytes());
+        let _ = message.try_extend_from_slice(&nonce);
+
+        reviewed = network_review(network)
+            .intro("Prove Ownership")
+            .field("Ownership", &|w| {
+                write!(w, "Prove ownership of ")?;
+                write_address(w, network, address)?;
+                Ok(write!(w, " to {service}")?)
+            })
+            .action("Sign Proof?")
+            .show();
+        reviewed.map_err(|_| CryptographyError::NoneError)
+    })
+    .is_err()
+    {
+        reject::<()>(refusal(reviewed)).await;
+    }
+
+    let tagged;
+    let message: &[u8] = if scheme == DerivationScheme::Bip340 {
+        tagged = tagged_hash(OWNERSHIP_PROOF_TAG, &message);
+        &tagged
+    } else {
+        &message
+    };
+    if let Some(sig) = { sign(&path, scheme, message).ok() } {
+        io.result_final(&sig[..]).await;
     } else {
         reject::<()>(SyscallError::Unspecified as u16).await;
     }
 }
 
+/// Labels the key a key agreement returns.
+const KEY_AGREEMENT_LABEL: &[u8] = b"alamgu-example x25519 key agreement";
+
+/// The X25519 shared secret with `peer` of the key at `path`, and our X25519 public key. Peers
+/// of small order, which would give an all-zero secret, are refused.
+fn x25519_shared_secret(
+    path: &[u32],
+    scheme: DerivationScheme,
+    peer: &[u8; 32],
+) -> Option<(Zeroizing<[u8; 32]>, [u8; 32])> {
+    let (shared, public) = with_x25519_scalar(path, scheme, |scalar| {
+        (
+            Zeroizing::new(x25519(scalar, peer)),
+            x25519(scalar, &BASE_POINT),
+        )
+    })
+    .ok()?;
+    if *shared == [0; 32] {
+        return None;
+    }
+    Some((shared, public))
+}
+
+/// SHA-256 of `label`, the shared secret and both public keys, the smaller first so that both
+/// sides get the same key. The secret itself never leaves the device.
+fn key_agreement_kdf(
+    label: &[u8],
+    shared: &[u8; 32],
+    a: &[u8; 32],
+    b: &[u8; 32],
+) -> Zeroizing<[u8; 32]> {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    // Labels are constants of at most 64 bytes.
+    let mut input = Zeroizing::new([0u8; 64 + 3 * 32]);
+    let mut length = 0;
+    for part in [label, &shared[..], &first[..], &second[..]] {
+        input[length..length + part.len()].copy_from_slice(part);
+        length += part.len();
+    }
+    let mut key = Zeroizing::new([0u8; 32]);
+    unsafe {
+        cx_hash_sha256(input.as_ptr(), length, key.as_mut_ptr(), key.len());
+    }
+    key
+}
+
+pub type PeerKeyParserImplT =
+    impl AsyncParser<Array<Byte, 32>, ByteStream> + HasOutput<Array<Byte, 32>, Output = [u8; 32]>;
+pub const PEER_KEY_PARSER: PeerKeyParserImplT = DefaultInterp;
+
+pub async fn key_agreement_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
+    let input = match io.get_params::<2>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let peer = parse_exactly(&PEER_KEY_PARSER, &mut input[0].clone()).await;
+    let path = parse_path(encoding, &mut input[1].clone()).await;
+
+    if scheme == DerivationScheme::Bip340 {
+        reject::<()>(SyscallError::NotSupported as u16).await;
+    }
+    if !is_app_path(&path, scheme) {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+
+    // Small-order peers are refused before the user is asked to share a key.
+    let (shared, public) = match x25519_shared_secret(&path, scheme, &peer) {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let network = settings.network();
+    let mut reviewed = Ok(());
+    if with_public_key(&path, scheme, |key| {
+        reviewed = network_review(network)
+            .intro("Key Agreement")
+            .field("Account", &|w| write_address(w, network, key))
+            .field("Peer", &|w| write_hex(w, &peer))
+            .action("Share Key?")
+            .show();
+        reviewed.map_err(|_| CryptographyError::NoneError)
+    })
+    .is_err()
+    {
+        reject::<()>(refusal(reviewed)).await;
+    }
+
+    let key = key_agreement_kdf(KEY_AGREEMENT_LABEL, &shared, &public, &peer);
+    io.result_final(&key[..]).await;
+}
+
+/// Labels the key memos are encrypted with, so that it differs from the key a key agreement
+/// with the same peer returns.
+const MEMO_KEY_LABEL: &[u8] = b"alamgu-example memo";
+
+pub type EncryptedMemoParserImplT = impl AsyncParser<EncryptedMemo, ByteStream>
+    + HasOutput<
+        EncryptedMemo,
+        Output = (
+            [u8; 32],
+            ([u8; NONCE_LEN], ArrayVec<u8, MAX_ENCRYPTED_MEMO_LEN>),
+        ),
+    >;
+pub const ENCRYPTED_MEMO_PARSER: EncryptedMemoParserImplT =
+    (DefaultInterp, (DefaultInterp, SubInterp(DefaultInterp)));
+
+pub async fn decrypt_memo_apdu(
+    io: HostIO,
+    encoding: PathEncoding,
+    scheme: DerivationScheme,
+    settings: Settings,
+) {
+    let input = match io.get_params::<2>() {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+
+    let (sender, (nonce, ciphertext)) =
+        parse_exactly(&ENCRYPTED_MEMO_PARSER, &mut input[0].clone()).await;
+    let path = parse_path(encoding, &mut input[1].clone()).await;
+
+    if scheme == DerivationScheme::Bip340 {
+        reject::<()>(SyscallError::NotSupported as u16).await;
+    }
+    if !is_app_path(&path, scheme) || ciphertext.len() < TAG_LEN {
+        reject::<()>(SyscallError::InvalidParameter as u16).await;
+    }
+
+    let (shared, public) = match x25519_shared_secret(&path, scheme, &sender) {
+        Some(v) => v,
+        None => reject(SyscallError::InvalidParameter as u16).await,
+    };
+    let key = key_agreement_kdf(MEMO_KEY_LABEL, &shared, &public, &sender);
+
+    let length = ciphertext.len() - TAG_LEN;
+    let mut tag = [0; TAG_LEN];
+    tag.copy_from_slice(&ciphertext[length..]);
+    let mut memo = Zeroizing::new([0u8; MAX_MEMO_LEN]);
+    memo[..length].copy_from_slice(&ciphertext[..length]);
+    let memo = &mut memo[..length];
+
+    if xchacha20poly1305::decrypt(&key, &nonce, &[], memo, &tag).is_err() {
+        scroller("ERROR", |w| {
+            Ok(write!(
+                w,
+                "Memo could not be decrypted: it is not for this account, or it was modified"
+            )?)
+        });
+        reject::<()>(SyscallError::Security as u16).await;
+    }
+
+    // The memo is only shown, never returned to the host: as text if it is printable ASCII, and
+    // as hex otherwise.
+    let printable = memo.iter().all(|c| (b' '..=b'~').contains(c));
+    let network = settings.network();
+    let mut reviewed = Ok(());
+    if with_public_key(&path, scheme, |key| {
+        reviewed = network_review(network)
+            .intro("Read Memo")
+            .field("To", &|w| write_address(w, network, key))
+            .field("From", &|w| write_hex(w, &sender))
+            .field(if printable { "Memo" } else { "Memo (hex)" }, &|w| {
+                if printable {
+                    Ok(w.write_str(core::str::from_utf8(memo).unwrap_or_default())?)
+                } else {
+                    write_hex(w, memo)
+                }
+            })
+            .action("Close Memo?")
+            .show();
+        reviewed.map_err(|_| CryptographyError::NoneError)
+    })
+    .is_err()
+    {
+        reject::<()>(refusal(reviewed)).await;
+    }
+
+    io.result_final(&[]).await;
+}
+
 pub type APDUsFuture = impl Future<Output = ()>;
 
 #[inline(never)]
-pub fn handle_apdu_async(io: HostIO, ins: Ins, settings: Settings) -> APDUsFuture {
+pub fn handle_apdu_async(io: HostIO, ins: Ins, p1: u8, p2: u8, settings: Settings) -> APDUsFuture {
     trace!("Constructing future");
+    // Only instructions that take a path accept a non-zero P1 or P2, and only valid values.
+    let encoding = PathEncoding::try_from(p1).unwrap_or(PathEncoding::LittleEndian);
+    let scheme = DerivationScheme::try_from(p2).unwrap_or(DerivationScheme::Legacy);
     async move {
         trace!("Dispatching");
         match ins {
@@ -167,18 +1243,48 @@ pub fn handle_apdu_async(io: HostIO, ins: Ins, settings: Settings) -> APDUsFutur
                 io.result_final(&rv).await;
             }
             Ins::VerifyAddress => {
-                NoinlineFut(get_address_apdu(io, true)).await;
+                NoinlineFut(get_address_apdu(io, true, encoding, scheme, settings)).await;
             }
             Ins::GetPubkey => {
-                NoinlineFut(get_address_apdu(io, false)).await;
+                NoinlineFut(get_address_apdu(io, false, encoding, scheme, settings)).await;
             }
             Ins::Sign => {
                 trace!("Handling sign");
-                NoinlineFut(sign_apdu(io, settings)).await;
+                NoinlineFut(sign_apdu(io, encoding, scheme, settings)).await;
+            }
+            Ins::ProvideAssetDescriptor => {
+                NoinlineFut(provide_asset_descriptor_apdu(io)).await;
+            }
+            Ins::ProveOwnership => {
+                NoinlineFut(prove_ownership_apdu(io, encoding, scheme, settings)).await;
+            }
+            Ins::KeyAgreement => {
+                NoinlineFut(key_agreement_apdu(io, encoding, scheme, settings)).await;
+            }
+            Ins::DecryptMemo => {
+                NoinlineFut(decrypt_memo_apdu(io, encoding, scheme, settings)).await;
             }
+            Ins::GetExtendedPubkey => {
+                NoinlineFut(get_extended_pubkey_apdu(io, encoding, scheme, settings)).await;
+            }
+            Ins::SignBatch => {
+                NoinlineFut(sign_batch_apdu(io, encoding, scheme, settings)).await;
+            }
+            Ins::SignContainer => {
+                NoinlineFut(sign_container_apdu(io, scheme, settings)).await;
+            }
+            #[cfg(feature = "debug_instructions")]
             Ins::TestParsers => {
                 NoinlineFut(test_parsers(io)).await;
             }
+            #[cfg(feature = "debug_instructions")]
+            Ins::TestBip340 => {
+                NoinlineFut(test_bip340(io)).await;
+            }
+            #[cfg(feature = "debug_instructions")]
+            Ins::TestSlip10 => {
+                NoinlineFut(test_slip10(io)).await;
+            }
             Ins::GetVersionStr => {}
             Ins::Exit => ledger_device_sdk::exit_app(0),
         }
@@ -1,4 +1,5 @@
 use core::convert::TryFrom;
+use core::marker::PhantomData;
 use ledger_device_sdk::io::{ApduHeader, StatusWords};
 use ledger_parser_combinators::core_parsers::*;
 use ledger_parser_combinators::endianness::*;
@@ -6,11 +7,138 @@ use num_enum::TryFromPrimitive;
 
 // Payload for a public key request
 pub type Bip32Key = DArray<Byte, U32<{ Endianness::Little }>, 10>;
+// The same with big endian steps, as docs/apdu.md has always described it.
+pub type Bip32KeyBigEndian = DArray<Byte, U32<{ Endianness::Big }>, 10>;
+// Just the account of a path that is otherwise the app's default one.
+pub type AccountIndex = U32<{ Endianness::Big }>;
+
+/// How a derivation path parameter is encoded, selected by P1 of the instructions that take one.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum PathEncoding {
+    /// `Bip32Key`, which is what the app has always read.
+    LittleEndian = 0,
+    /// `Bip32KeyBigEndian`.
+    BigEndian = 1,
+    /// `AccountIndex`.
+    AccountIndex = 2,
+}
+
+/// How keys are derived from the seed, and so which kind of key and signature an account uses,
+/// selected by P2 of the instructions that take a path.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum DerivationScheme {
+    /// What the app has always done: the OS's BIP32-Ed25519 derivation, with the first half of
+    /// the derived key then used as an RFC 8032 private key, which hashes it again.
+    Legacy = 0,
+    /// SLIP-10, hardened steps only.
+    Slip10 = 1,
+    /// BIP32-Ed25519 (Khovratovich and Law), with the derived key used as an extended key, as
+    /// Cardano does.
+    Bip32Ed25519 = 2,
+    /// BIP32 on secp256k1, with BIP340 Schnorr signatures and x-only public keys. These keys
+    /// have no X25519 counterpart.
+    Bip340 = 3,
+}
 
 // Payload for a signature request, content-agnostic.
 pub type SignPayload = DArray<U32<{ Endianness::Little }>, Byte, { usize::MAX }>;
 
-pub type SignParameters = (SignPayload, Bip32Key);
+// From one to N `I`s back to back, with no count before them: they run to the end of the
+// parameter.
+pub struct Concatenated<I, const N: usize>(PhantomData<I>);
+
+// The second parameter holds the paths of the keys signing, one per input of the transaction,
+// each encoded as P1 selects; Bip32Key is the default encoding. A single path is a transaction
+// with one input, as the parameter has always been. The same path can't be given twice.
+pub type SignPaths = Concatenated<Bip32Key, MAX_SIGN_INPUTS>;
+pub type SignParameters = (SignPayload, SignPaths);
+pub const MAX_SIGN_INPUTS: usize = 4;
+
+// Transactions in a batch, each read as a signature request's (see SIGN_BATCH in docs/apdu.md).
+pub const MAX_BATCH_LEN: usize = 32;
+
+// An input of a partially signed transaction (see SIGN_CONTAINER in docs/apdu.md for the whole
+// container): the public key that signs it, the path of that key if the device is to sign it or
+// no steps otherwise, and its signature, or nothing while it is unsigned. Change hints are
+// Bip32Keys.
+pub type ContainerInput = (Array<Byte, 32>, (Bip32Key, DArray<Byte, Byte, 64>));
+pub const CONTAINER_VERSION: u8 = 1;
+pub const MAX_CONTAINER_INPUTS: usize = 16;
+pub const MAX_CHANGE_HINTS: usize = 4;
+
+pub type AssetId = Array<Byte, 32>;
+
+// Payload for an asset descriptor: asset id, ticker, decimals and chain id, followed by a
+// signature over them by the key the app was built with.
+pub type AssetDescriptor = (
+    AssetId,
+    (
+        DArray<Byte, Byte, MAX_TICKER_LEN>,
+        (Byte, U32<{ Endianness::Little }>),
+    ),
+);
+pub type AssetDescriptorParameters = (AssetDescriptor, Array<Byte, 64>);
+
+pub const MAX_TICKER_LEN: usize = 10;
+
+// The one transaction the app can clear sign: tag, chain id, asset id, recipient, amount in base
+// units, fee in base units of the native asset, nonce and expiry height. Asset id zero is the
+// chain's native asset.
+pub type Transfer = (
+    Byte,
+    (
+        U32<{ Endianness::Little }>,
+        (
+            AssetId,
+            (
+                Array<Byte, 32>,
+                (
+                    U64<{ Endianness::Little }>,
+                    (
+                        U64<{ Endianness::Little }>,
+                        (U64<{ Endianness::Little }>, U64<{ Endianness::Little }>),
+                    ),
+                ),
+            ),
+        ),
+    ),
+);
+
+// Payload for an ownership proof: a nonce chosen by the service asking for the proof, and the
+// service's name as printable ASCII.
+pub type OwnershipChallenge = (Array<Byte, 32>, DArray<Byte, Byte, MAX_SERVICE_LEN>);
+pub type OwnershipProofParameters = (OwnershipChallenge, Bip32Key);
+pub const MAX_SERVICE_LEN: usize = 64;
+
+// Payload for a key agreement: the peer's X25519 public key.
+pub type KeyAgreementParameters = (Array<Byte, 32>, Bip32Key);
+
+// Payload for reading a memo: the sender's X25519 public key, the 24 byte nonce, and the
+// XChaCha20-Poly1305 ciphertext followed by its 16 byte tag.
+pub type EncryptedMemo = (
+    Array<Byte, 32>,
+    (
+        Array<Byte, 24>,
+        DArray<U16<{ Endianness::Little }>, Byte, MAX_ENCRYPTED_MEMO_LEN>,
+    ),
+);
+pub type DecryptMemoParameters = (EncryptedMemo, Bip32Key);
+pub const MAX_MEMO_LEN: usize = 256;
+pub const MAX_ENCRYPTED_MEMO_LEN: usize = MAX_MEMO_LEN + 16;
+
+pub const TRANSFER_TAG: u8 = 1;
+pub const TRANSFER_LENGTH: usize = 1 + 4 + 32 + 32 + 8 * 4;
+
+// Status words of this app's own, besides the SDK's StatusWords and SyscallError.
+#[repr(u16)]
+#[derive(Debug)]
+pub enum AppStatusWords {
+    TrailingData = 0x6A80,
+    TransactionTooLarge = 0x6A84,
+    ReviewTooLong = 0x6A85,
+}
 
 #[repr(u8)]
 #[derive(Debug, TryFromPrimitive)]
@@ -19,7 +147,21 @@ pub enum Ins {
     VerifyAddress = 1,
     GetPubkey = 2,
     Sign = 3,
+    ProvideAssetDescriptor = 4,
+    ProveOwnership = 5,
+    KeyAgreement = 6,
+    DecryptMemo = 7,
+    GetExtendedPubkey = 8,
+    SignBatch = 9,
+    SignContainer = 10,
+    // Debug-only instructions are not available in release builds, where they are unknown
+    // instructions and refused with BadIns.
+    #[cfg(feature = "debug_instructions")]
     TestParsers = 0x20,
+    #[cfg(feature = "debug_instructions")]
+    TestBip340 = 0x21,
+    #[cfg(feature = "debug_instructions")]
+    TestSlip10 = 0x22,
     GetVersionStr = 0xfe,
     Exit = 0xff,
 }
@@ -31,9 +173,34 @@ impl TryFrom<ApduHeader> for Ins {
             ApduHeader {
                 cla: 0,
                 ins,
-                p1: 0,
-                p2: 0,
-            } => Self::try_from(ins).map_err(|_| StatusWords::BadIns),
+                p1,
+                p2,
+            } => match Self::try_from(ins) {
+                // P1 and P2 are the path encoding and derivation scheme for instructions that take
+                // a path, and 0 otherwise.
+                Ok(
+                    ins @ (Ins::VerifyAddress
+                    | Ins::GetPubkey
+                    | Ins::Sign
+                    | Ins::ProveOwnership
+                    | Ins::KeyAgreement
+                    | Ins::DecryptMemo
+                    | Ins::GetExtendedPubkey
+                    | Ins::SignBatch),
+                ) if PathEncoding::try_from(p1).is_ok()
+                    && DerivationScheme::try_from(p2).is_ok() =>
+                {
+                    Ok(ins)
+                }
+                // Paths in a container have a fixed encoding, so only P2 is used.
+                Ok(ins @ Ins::SignContainer)
+                    if p1 == 0 && DerivationScheme::try_from(p2).is_ok() =>
+                {
+                    Ok(ins)
+                }
+                Ok(ins) if p1 == 0 && p2 == 0 => Ok(ins),
+                _ => Err(StatusWords::BadIns),
+            },
             _ => Err(StatusWords::BadIns),
         }
     }
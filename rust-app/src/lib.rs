@@ -3,6 +3,7 @@
 #![feature(adt_const_params)]
 #![feature(str_internals)]
 #![feature(type_alias_impl_trait)]
+#![feature(impl_trait_in_assoc_type)]
 #![feature(const_mut_refs)]
 #![cfg_attr(all(target_family = "bolos", test), no_main)]
 #![cfg_attr(target_family = "bolos", feature(custom_test_frameworks))]
@@ -27,24 +28,50 @@ extern "C" fn sample_main() {
 
 pub mod interface;
 
+pub mod amount;
+
+pub mod hash_format;
+
+pub mod x25519;
+
+pub mod xchacha20poly1305;
+
 #[cfg(target_family = "bolos")]
 pub mod utils;
 
-#[cfg(target_family = "bolos")]
+#[cfg(all(target_family = "bolos", feature = "debug_instructions"))]
 pub mod test_parsers;
 
 #[cfg(target_family = "bolos")]
 pub mod implementation;
 
 #[cfg(target_family = "bolos")]
+pub mod assets;
+
+#[cfg(target_family = "bolos")]
+pub mod derivation;
+
+#[cfg(all(
+    target_family = "bolos",
+    not(any(target_os = "stax", target_os = "flex"))
+))]
 pub mod menu;
 
 #[cfg(target_family = "bolos")]
 pub mod settings;
 
-#[cfg(target_family = "bolos")]
+#[cfg(all(
+    target_family = "bolos",
+    not(any(target_os = "stax", target_os = "flex"))
+))]
 pub mod main_nanos;
 
+#[cfg(all(target_family = "bolos", any(target_os = "stax", target_os = "flex")))]
+pub mod home;
+
+#[cfg(all(target_family = "bolos", any(target_os = "stax", target_os = "flex")))]
+pub mod main_stax;
+
 #[cfg(all(target_family = "bolos", test))]
 use core::panic::PanicInfo;
 /// In case of runtime problems, return an internal error and exit the app
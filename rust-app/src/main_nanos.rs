@@ -60,11 +60,13 @@ pub fn app_main() {
         match evt {
             io::Event::Command(ins) => {
                 trace!("Command received");
+                let p1 = comm.borrow().get_apdu_metadata().p1;
+                let p2 = comm.borrow().get_apdu_metadata().p2;
                 let poll_rv = poll_apdu_handlers(
                     PinMut::as_mut(&mut states.0.borrow_mut()),
                     ins,
                     *hostio,
-                    |io, ins| handle_apdu_async(io, ins, idle_menu.settings),
+                    |io, ins| handle_apdu_async(io, ins, p1, p2, idle_menu.settings),
                 );
                 match poll_rv {
                     Ok(()) => {
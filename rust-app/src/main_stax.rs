@@ -0,0 +1,93 @@
+use crate::home::*;
+use crate::implementation::*;
+use crate::interface::*;
+use crate::settings::*;
+
+use alamgu_async_block::*;
+
+use ledger_device_sdk::io;
+use ledger_device_sdk::nbgl::init_comm;
+use ledger_log::{info, trace};
+
+use core::cell::RefCell;
+use core::pin::Pin;
+use pin_cell::*;
+
+#[allow(dead_code)]
+pub fn app_main() {
+    let comm: SingleThreaded<RefCell<io::Comm>> = SingleThreaded(RefCell::new(io::Comm::new()));
+    // NBGL review flows wait for touch events on this Comm themselves.
+    init_comm(&mut comm.borrow_mut());
+
+    let hostio_state: SingleThreaded<RefCell<HostIOState>> =
+        SingleThreaded(RefCell::new(HostIOState::new(unsafe {
+            core::mem::transmute(&comm.0)
+        })));
+    let hostio: SingleThreaded<HostIO> =
+        SingleThreaded(HostIO(unsafe { core::mem::transmute(&hostio_state.0) }));
+    let states_backing: SingleThreaded<PinCell<Option<APDUsFuture>>> =
+        SingleThreaded(PinCell::new(None));
+    let states: SingleThreaded<Pin<&PinCell<Option<APDUsFuture>>>> =
+        SingleThreaded(Pin::static_ref(unsafe {
+            core::mem::transmute(&states_backing.0)
+        }));
+
+    let settings = Settings;
+    info!("Alamgu Example {}", env!("CARGO_PKG_VERSION"));
+    info!(
+        "State sizes\ncomm: {}\nstates: {}",
+        core::mem::size_of::<io::Comm>(),
+        core::mem::size_of::<Option<APDUsFuture>>()
+    );
+
+    show_home(&settings);
+    loop {
+        // The home screen, including its quit button and settings page, handles touches
+        // itself; we only need to wait for APDUs.
+        let evt = comm.borrow_mut().next_event::<Ins>();
+        if let io::Event::Command(ins) = evt {
+            trace!("Command received");
+            let p1 = comm.borrow().get_apdu_metadata().p1;
+            let p2 = comm.borrow().get_apdu_metadata().p2;
+            let poll_rv = poll_apdu_handlers(
+                PinMut::as_mut(&mut states.0.borrow_mut()),
+                ins,
+                *hostio,
+                |io, ins| handle_apdu_async(io, ins, p1, p2, settings),
+            );
+            match poll_rv {
+                Ok(()) => {
+                    trace!("APDU accepted; sending response");
+                    comm.borrow_mut().reply_ok();
+                    trace!("Replied");
+                }
+                Err(sw) => {
+                    PinMut::as_mut(&mut states.0.borrow_mut()).set(None);
+                    comm.borrow_mut().reply(sw);
+                }
+            };
+            // Review flows replace the home screen; bring it back once the APDU is done.
+            if states.borrow().is_none() {
+                show_home(&settings);
+            }
+            trace!("Command done");
+        }
+    }
+}
+
+// We are single-threaded in fact, albeit with nontrivial code flow. We don't need to worry about
+// full atomicity of the below globals.
+struct SingleThreaded<T>(T);
+unsafe impl<T> Send for SingleThreaded<T> {}
+unsafe impl<T> Sync for SingleThreaded<T> {}
+impl<T> core::ops::Deref for SingleThreaded<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+impl<T> core::ops::DerefMut for SingleThreaded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
@@ -23,8 +23,11 @@ pub enum IdleMenu {
 }
 
 pub enum SettingsSubMenu {
-    EnableBlindSigning,
-    DisableBlindSigning,
+    BlindSigning,
+    Network,
+    ExpertMode,
+    HashFormat,
+    GroupHashes,
     Back,
 }
 
@@ -44,14 +47,12 @@ impl Menu for IdleMenuWithSettings {
             AppMain => self.idle_menu = Exit,
             ShowVersion => self.idle_menu = AppMain,
             Settings(None) => self.idle_menu = ShowVersion,
-            Settings(Some(Back)) => {
-                if self.settings.get() == 1 {
-                    self.idle_menu = Settings(Some(DisableBlindSigning))
-                } else {
-                    self.idle_menu = Settings(Some(EnableBlindSigning))
-                }
-            }
-            Settings(Some(_)) => self.idle_menu = Settings(Some(Back)),
+            Settings(Some(Back)) => self.idle_menu = Settings(Some(GroupHashes)),
+            Settings(Some(GroupHashes)) => self.idle_menu = Settings(Some(HashFormat)),
+            Settings(Some(HashFormat)) => self.idle_menu = Settings(Some(ExpertMode)),
+            Settings(Some(ExpertMode)) => self.idle_menu = Settings(Some(Network)),
+            Settings(Some(Network)) => self.idle_menu = Settings(Some(BlindSigning)),
+            Settings(Some(BlindSigning)) => self.idle_menu = Settings(Some(Back)),
             Exit => self.idle_menu = Settings(None),
         };
     }
@@ -62,14 +63,12 @@ impl Menu for IdleMenuWithSettings {
             AppMain => self.idle_menu = ShowVersion,
             ShowVersion => self.idle_menu = Settings(None),
             Settings(None) => self.idle_menu = Exit,
-            Settings(Some(Back)) => {
-                if self.settings.get() == 1 {
-                    self.idle_menu = Settings(Some(DisableBlindSigning))
-                } else {
-                    self.idle_menu = Settings(Some(EnableBlindSigning))
-                }
-            }
-            Settings(Some(_)) => self.idle_menu = Settings(Some(Back)),
+            Settings(Some(Back)) => self.idle_menu = Settings(Some(BlindSigning)),
+            Settings(Some(Network)) => self.idle_menu = Settings(Some(ExpertMode)),
+            Settings(Some(ExpertMode)) => self.idle_menu = Settings(Some(HashFormat)),
+            Settings(Some(HashFormat)) => self.idle_menu = Settings(Some(GroupHashes)),
+            Settings(Some(GroupHashes)) => self.idle_menu = Settings(Some(Back)),
+            Settings(Some(BlindSigning)) => self.idle_menu = Settings(Some(Network)),
             Exit => self.idle_menu = AppMain,
         };
     }
@@ -81,21 +80,33 @@ impl Menu for IdleMenuWithSettings {
             AppMain => None,
             ShowVersion => None,
             Settings(None) => {
-                if self.settings.get() == 1 {
-                    self.idle_menu = Settings(Some(DisableBlindSigning))
-                } else {
-                    self.idle_menu = Settings(Some(EnableBlindSigning))
-                };
+                self.idle_menu = Settings(Some(BlindSigning));
+                None
+            }
+            Settings(Some(BlindSigning)) => {
+                self.settings
+                    .set_blind_signing(self.settings.blind_signing().next());
                 None
             }
-            Settings(Some(EnableBlindSigning)) => {
-                self.settings.set(&1);
-                self.idle_menu = Settings(Some(DisableBlindSigning));
+            Settings(Some(Network)) => {
+                self.settings.set_network(match self.settings.network() {
+                    crate::settings::Network::Mainnet => crate::settings::Network::Testnet,
+                    crate::settings::Network::Testnet => crate::settings::Network::Mainnet,
+                });
                 None
             }
-            Settings(Some(DisableBlindSigning)) => {
-                self.settings.set(&0);
-                self.idle_menu = Settings(Some(EnableBlindSigning));
+            Settings(Some(ExpertMode)) => {
+                self.settings.set_expert_mode(!self.settings.expert_mode());
+                None
+            }
+            Settings(Some(HashFormat)) => {
+                self.settings
+                    .set_hash_format(self.settings.hash_format().next());
+                None
+            }
+            Settings(Some(GroupHashes)) => {
+                self.settings
+                    .set_group_hashes(!self.settings.group_hashes());
                 None
             }
             Settings(Some(Back)) => {
@@ -131,17 +142,46 @@ impl Menu for IdleMenuWithSettings {
                     bold: true,
                 },
             ),
-            Settings(Some(EnableBlindSigning)) => (
+            Settings(Some(BlindSigning)) => (
                 MenuLabelTop::Text("Blind Signing"),
                 MenuLabelBottom {
-                    text: "Disabled",
+                    text: self.settings.blind_signing().name(),
                     bold: false,
                 },
             ),
-            Settings(Some(DisableBlindSigning)) => (
-                MenuLabelTop::Text("Blind Signing"),
+            Settings(Some(Network)) => (
+                MenuLabelTop::Text("Network"),
+                MenuLabelBottom {
+                    text: self.settings.network().name(),
+                    bold: false,
+                },
+            ),
+            Settings(Some(ExpertMode)) => (
+                MenuLabelTop::Text("Expert Mode"),
+                MenuLabelBottom {
+                    text: if self.settings.expert_mode() {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    },
+                    bold: false,
+                },
+            ),
+            Settings(Some(HashFormat)) => (
+                MenuLabelTop::Text("Hash Format"),
+                MenuLabelBottom {
+                    text: self.settings.hash_format().name(),
+                    bold: false,
+                },
+            ),
+            Settings(Some(GroupHashes)) => (
+                MenuLabelTop::Text("Group Hashes"),
                 MenuLabelBottom {
-                    text: "Enabled",
+                    text: if self.settings.group_hashes() {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    },
                     bold: false,
                 },
             ),
@@ -1,9 +1,23 @@
+use crate::hash_format::HashFormat;
 use ledger_device_sdk::nvm::*;
 use ledger_device_sdk::NVMData;
 
+// One byte per setting.
+pub const SETTINGS_SIZE: usize = 5;
+const BLIND_SIGNING: usize = 0;
+const NETWORK: usize = 1;
+const EXPERT_MODE: usize = 2;
+const HASH_FORMAT: usize = 3;
+const GROUP_HASHES: usize = 4;
+
 // This is necessary to store the object in NVM and not in RAM
 #[link_section = ".nvm_data"]
-static mut SETTINGS: NVMData<AtomicStorage<u8>> = NVMData::new(AtomicStorage::new(&0));
+static mut SETTINGS: NVMData<AtomicStorage<[u8; SETTINGS_SIZE]>> =
+    NVMData::new(AtomicStorage::new(&[0; SETTINGS_SIZE]));
+
+// Blind signing for one signature or one session is kept in RAM, so that it is gone when the app
+// exits and never costs an NVM write.
+static mut TEMPORARY_BLIND_SIGNING: BlindSigning = BlindSigning::Disabled;
 
 #[derive(Clone, Copy)]
 pub struct Settings;
@@ -14,17 +28,181 @@ impl Default for Settings {
     }
 }
 
+/// The network the app signs for. Stored as 0 for mainnet and 1 for testnet, so that it can back
+/// an on/off switch.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    /// The only chain id transactions may carry on this network.
+    pub fn chain_id(self) -> u32 {
+        match self {
+            Network::Mainnet => 1,
+            Network::Testnet => 2,
+        }
+    }
+
+    pub fn address_prefix(self) -> &'static str {
+        match self {
+            Network::Mainnet => "exa",
+            Network::Testnet => "texa",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Mainnet => "Mainnet",
+            Network::Testnet => "Testnet",
+        }
+    }
+
+    /// Shown at the start of every review when not on mainnet.
+    pub fn banner(self) -> Option<&'static str> {
+        match self {
+            Network::Mainnet => None,
+            Network::Testnet => Some("TESTNET"),
+        }
+    }
+}
+
+/// When transactions the app cannot display may be signed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlindSigning {
+    Disabled,
+    /// For the next blind signature only.
+    OneShot,
+    /// Until the app exits.
+    Session,
+    Always,
+}
+
+impl BlindSigning {
+    pub fn enabled(self) -> bool {
+        self != BlindSigning::Disabled
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlindSigning::Disabled => "Disabled",
+            BlindSigning::OneShot => "One signature",
+            BlindSigning::Session => "This session",
+            BlindSigning::Always => "Always",
+        }
+    }
+
+    /// The mode after this one, for cycling through them in a menu. Modes that turn themselves
+    /// off come first.
+    pub fn next(self) -> Self {
+        match self {
+            BlindSigning::Disabled => BlindSigning::OneShot,
+            BlindSigning::OneShot => BlindSigning::Session,
+            BlindSigning::Session => BlindSigning::Always,
+            BlindSigning::Always => BlindSigning::Disabled,
+        }
+    }
+}
+
 impl Settings {
+    /// Blind signing is always on when its byte is set, as before there were other modes;
+    /// otherwise the mode kept in RAM says for how long it is on, if at all.
+    #[inline(never)]
+    pub fn blind_signing(&self) -> BlindSigning {
+        if self.read(BLIND_SIGNING) == 1 {
+            return BlindSigning::Always;
+        }
+        unsafe { TEMPORARY_BLIND_SIGNING }
+    }
+
+    pub fn set_blind_signing(&mut self, mode: BlindSigning) {
+        let always = (mode == BlindSigning::Always) as u8;
+        if self.read(BLIND_SIGNING) != always {
+            self.write(BLIND_SIGNING, always);
+        }
+        unsafe {
+            TEMPORARY_BLIND_SIGNING = match mode {
+                BlindSigning::OneShot | BlindSigning::Session => mode,
+                BlindSigning::Disabled | BlindSigning::Always => BlindSigning::Disabled,
+            };
+        }
+    }
+
+    /// Called after every blind signature, to use up a one-shot permission.
+    pub fn blind_signature_done(&mut self) {
+        if self.blind_signing() == BlindSigning::OneShot {
+            self.set_blind_signing(BlindSigning::Disabled);
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        match self.read(NETWORK) {
+            1 => Network::Testnet,
+            _ => Network::Mainnet,
+        }
+    }
+
+    pub fn set_network(&mut self, network: Network) {
+        self.write(
+            NETWORK,
+            match network {
+                Network::Mainnet => 0,
+                Network::Testnet => 1,
+            },
+        )
+    }
+
+    /// Whether reviews show every field of a transaction rather than just the essentials.
+    pub fn expert_mode(&self) -> bool {
+        self.read(EXPERT_MODE) == 1
+    }
+
+    pub fn set_expert_mode(&mut self, enabled: bool) {
+        self.write(EXPERT_MODE, enabled as u8)
+    }
+
+    /// How transaction hashes are written: 0 for base64url, 1 for hex and 2 for base58.
+    pub fn hash_format(&self) -> HashFormat {
+        match self.read(HASH_FORMAT) {
+            1 => HashFormat::Hex,
+            2 => HashFormat::Base58,
+            _ => HashFormat::Base64Url,
+        }
+    }
+
+    pub fn set_hash_format(&mut self, format: HashFormat) {
+        self.write(
+            HASH_FORMAT,
+            match format {
+                HashFormat::Base64Url => 0,
+                HashFormat::Hex => 1,
+                HashFormat::Base58 => 2,
+            },
+        )
+    }
+
+    /// Whether hashes are shown in blocks of four characters.
+    pub fn group_hashes(&self) -> bool {
+        self.read(GROUP_HASHES) == 1
+    }
+
+    pub fn set_group_hashes(&mut self, grouped: bool) {
+        self.write(GROUP_HASHES, grouped as u8)
+    }
+
     #[inline(never)]
-    pub fn get(&self) -> u8 {
+    fn read(&self, index: usize) -> u8 {
         let settings = unsafe { SETTINGS.get_mut() };
-        return *settings.get_ref();
+        settings.get_ref()[index]
     }
 
     // The inline(never) is important. Otherwise weird segmentation faults happen on speculos.
     #[inline(never)]
-    pub fn set(&mut self, v: &u8) {
+    fn write(&mut self, index: usize, v: u8) {
         let settings = unsafe { SETTINGS.get_mut() };
-        settings.update(v);
+        let mut new = *settings.get_ref();
+        new[index] = v;
+        settings.update(&new);
     }
 }
@@ -1,28 +1,160 @@
+use crate::derivation::{slip10_with_seed, Secp256k1Key};
+use crate::interface::Bip32Key;
 use crate::utils::*;
 use alamgu_async_block::*;
 use arrayvec::ArrayVec;
 use core::fmt::Write;
+use core::future::Future;
+use ledger_device_sdk::io::SyscallError;
 use ledger_parser_combinators::async_parser::*;
 use ledger_parser_combinators::core_parsers::*;
 use ledger_parser_combinators::endianness::*;
 use ledger_parser_combinators::interp::*;
 
 // Try out all possible param types
-pub type TestParsersSchema = ((BytesParams, U16Params), (U64Params, DArrayParams));
+pub type TestParsersSchema = (
+    ((BytesParams, U16Params), (U64Params, DArrayParams)),
+    (
+        (EnumParams, OptionParams),
+        (ByteStringParams, (LEB128Params, NestedParams)),
+    ),
+);
 
 pub type BytesParams = (Byte, Array<Byte, 32>);
 pub type U16Params = (U16<{ Endianness::Big }>, U16<{ Endianness::Little }>);
 pub type U32Params = (U32<{ Endianness::Big }>, U32<{ Endianness::Little }>);
 pub type U64Params = (U64<{ Endianness::Big }>, U64<{ Endianness::Little }>);
 pub type DArrayParams = (DArray<Byte, Byte, 24>, DArray<Byte, U32Params, 4>);
+pub type EnumParams = (TestEnum, TestEnum);
+pub type OptionParams = (
+    Optional<U32<{ Endianness::Little }>>,
+    Optional<DArray<Byte, Byte, 8>>,
+);
+pub type ByteStringParams = DArray<U16<{ Endianness::Little }>, Byte, 64>;
+pub type LEB128Params = (LEB128, LEB128);
+pub type NestedEntry = (LEB128, Optional<Byte>);
+pub type NestedParams = (
+    U32<{ Endianness::Little }>,
+    (Byte, DArray<Byte, NestedEntry, 4>),
+);
+
+/// Unsigned LEB128 varint, decoded to a u64. Encodings that are longer than
+/// necessary or overflow 64 bits are rejected.
+pub struct LEB128;
+
+impl HasOutput<LEB128> for DefaultInterp {
+    type Output = u64;
+}
+
+impl<BS: Readable> AsyncParser<LEB128, BS> for DefaultInterp {
+    type State<'c>
+        = impl Future<Output = Self::Output> + 'c
+    where
+        BS: 'c;
+    fn parse<'a: 'c, 'b: 'c, 'c>(&'b self, input: &'a mut BS) -> Self::State<'c> {
+        async move {
+            let mut value: u64 = 0;
+            let mut shift = 0;
+            loop {
+                let [byte] = input.read().await;
+                // The tenth byte only has room for the top bit of a u64, and a
+                // trailing zero byte means the encoding is not minimal.
+                if (shift == 63 && byte > 1) || (shift > 0 && byte == 0) {
+                    reject::<()>(SyscallError::InvalidParameter as u16).await;
+                }
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte & 0x80 == 0 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+    }
+}
+
+/// A value preceded by a presence byte: 0 when absent, 1 when present.
+pub struct Optional<S>(pub S);
+
+impl<S, P: HasOutput<S>> HasOutput<Optional<S>> for SubInterp<P> {
+    type Output = Option<P::Output>;
+}
+
+impl<S, BS: Readable, P: AsyncParser<S, BS>> AsyncParser<Optional<S>, BS> for SubInterp<P> {
+    type State<'c>
+        = impl Future<Output = Self::Output> + 'c
+    where
+        BS: 'c,
+        Self: 'c;
+    fn parse<'a: 'c, 'b: 'c, 'c>(&'b self, input: &'a mut BS) -> Self::State<'c> {
+        async move {
+            match <DefaultInterp as AsyncParser<Byte, BS>>::parse(&DefaultInterp, input).await {
+                0 => None,
+                1 => Some(self.0.parse(input).await),
+                _ => reject(SyscallError::InvalidParameter as u16).await,
+            }
+        }
+    }
+}
+
+/// Tagged union: a one-byte tag selects how the rest of the value is laid out.
+pub struct TestEnum;
+
+#[derive(Debug)]
+pub enum TestEnumValue {
+    Empty,
+    Number(u32),
+    Bytes(ArrayVec<u8, 8>),
+}
+
+impl HasOutput<TestEnum> for DefaultInterp {
+    type Output = TestEnumValue;
+}
+
+impl<BS: Readable> AsyncParser<TestEnum, BS> for DefaultInterp {
+    type State<'c>
+        = impl Future<Output = Self::Output> + 'c
+    where
+        BS: 'c;
+    fn parse<'a: 'c, 'b: 'c, 'c>(&'b self, input: &'a mut BS) -> Self::State<'c> {
+        async move {
+            match <DefaultInterp as AsyncParser<Byte, BS>>::parse(&DefaultInterp, input).await {
+                0 => TestEnumValue::Empty,
+                1 => TestEnumValue::Number(
+                    <DefaultInterp as AsyncParser<U32<{ Endianness::Little }>, BS>>::parse(
+                        &DefaultInterp,
+                        input,
+                    )
+                    .await,
+                ),
+                2 => TestEnumValue::Bytes(
+                    <SubInterp<DefaultInterp> as AsyncParser<DArray<Byte, Byte, 8>, BS>>::parse(
+                        &SubInterp(DefaultInterp),
+                        input,
+                    )
+                    .await,
+                ),
+                _ => reject(SyscallError::InvalidParameter as u16).await,
+            }
+        }
+    }
+}
 
 pub type TestParsersImplT<BS: Readable> =
     impl AsyncParser<TestParsersSchema, BS> + HasOutput<TestParsersSchema, Output = ()>;
 pub const fn test_parsers_parser<BS: Readable>() -> TestParsersImplT<BS> {
     Action(
         (
-            (bytes_params_parser(), u16_params_parser()),
-            (u64_params_parser(), darray_params_parser()),
+            (
+                (bytes_params_parser(), u16_params_parser()),
+                (u64_params_parser(), darray_params_parser()),
+            ),
+            (
+                (enum_params_parser(), option_params_parser()),
+                (
+                    byte_string_params_parser(),
+                    (leb128_params_parser(), nested_params_parser()),
+                ),
+            ),
         ),
         |_| scroller("Parse done", |w| Ok(write!(w, "")?)),
     )
@@ -74,8 +206,138 @@ const fn darray_params_parser<BS: Readable>() -> DArrayParamsT<BS> {
     )
 }
 
+pub type EnumParamsT<BS: Readable> =
+    impl AsyncParser<EnumParams, BS> + HasOutput<EnumParams, Output = ()>;
+const fn enum_params_parser<BS: Readable>() -> EnumParamsT<BS> {
+    Action(
+        (DefaultInterp, DefaultInterp),
+        |(v1, v2): (TestEnumValue, TestEnumValue)| {
+            scroller("Got Enum", |w| Ok(write!(w, "v1: {v1:?}, v2: {v2:?}")?))
+        },
+    )
+}
+
+pub type OptionParamsT<BS: Readable> =
+    impl AsyncParser<OptionParams, BS> + HasOutput<OptionParams, Output = ()>;
+const fn option_params_parser<BS: Readable>() -> OptionParamsT<BS> {
+    Action(
+        (
+            SubInterp(DefaultInterp),
+            SubInterp(SubInterp(DefaultInterp)),
+        ),
+        |(v1, v2): (Option<u32>, Option<ArrayVec<u8, 8>>)| {
+            scroller("Got Option", |w| Ok(write!(w, "v1: {v1:?}, v2: {v2:?}")?))
+        },
+    )
+}
+
+pub type ByteStringParamsT<BS: Readable> =
+    impl AsyncParser<ByteStringParams, BS> + HasOutput<ByteStringParams, Output = ()>;
+const fn byte_string_params_parser<BS: Readable>() -> ByteStringParamsT<BS> {
+    Action(SubInterp(DefaultInterp), |v: ArrayVec<u8, 64>| {
+        scroller("Got Byte String", |w| {
+            Ok(write!(w, "len: {}, v: {:02x?}", v.len(), v)?)
+        })
+    })
+}
+
+pub type LEB128ParamsT<BS: Readable> =
+    impl AsyncParser<LEB128Params, BS> + HasOutput<LEB128Params, Output = ()>;
+const fn leb128_params_parser<BS: Readable>() -> LEB128ParamsT<BS> {
+    Action((DefaultInterp, DefaultInterp), |(v1, v2): (u64, u64)| {
+        scroller("Got LEB128", |w| Ok(write!(w, "v1: {v1}, v2: {v2}")?))
+    })
+}
+
+pub type NestedEntryT<BS: Readable> =
+    impl AsyncParser<NestedEntry, BS> + HasOutput<NestedEntry, Output = ()>;
+const fn nested_entry_parser<BS: Readable>() -> NestedEntryT<BS> {
+    Action(
+        (DefaultInterp, SubInterp(DefaultInterp)),
+        |(amount, flag): (u64, Option<u8>)| {
+            scroller("Got Entry", |w| {
+                Ok(write!(w, "amount: {amount}, flag: {flag:?}")?)
+            })
+        },
+    )
+}
+
+pub type NestedParamsT<BS: Readable> =
+    impl AsyncParser<NestedParams, BS> + HasOutput<NestedParams, Output = ()>;
+const fn nested_params_parser<BS: Readable>() -> NestedParamsT<BS> {
+    Action(
+        (
+            DefaultInterp,
+            (DefaultInterp, SubInterp(nested_entry_parser())),
+        ),
+        |(id, (kind, entries)): (u32, (u8, ArrayVec<(), 4>))| {
+            scroller("Got Nested", |w| {
+                Ok(write!(
+                    w,
+                    "id: {id}, kind: {kind}, entries: {}",
+                    entries.len()
+                )?)
+            })
+        },
+    )
+}
+
 pub async fn test_parsers(io: HostIO) {
     let input = io.get_params::<1>().unwrap();
-    test_parsers_parser().parse(&mut input[0].clone()).await;
+    parse_exactly(&test_parsers_parser(), &mut input[0].clone()).await;
     io.result_final(&[]).await;
 }
+
+// A secp256k1 secret key, and a 32 byte message to sign with it.
+pub type TestBip340Schema = (Array<Byte, 32>, Array<Byte, 32>);
+
+pub type TestBip340ParserT<BS: Readable> = impl AsyncParser<TestBip340Schema, BS>
+    + HasOutput<TestBip340Schema, Output = ([u8; 32], [u8; 32])>;
+const fn test_bip340_parser<BS: Readable>() -> TestBip340ParserT<BS> {
+    (DefaultInterp, DefaultInterp)
+}
+
+/// Returns the x-only public key of a secret key the host gives, and a signature with it, so
+/// that the ts-tests can check the OS's BIP340 signing against BIP340's test vectors.
+pub async fn test_bip340(io: HostIO) {
+    let input = io.get_params::<1>().unwrap();
+    let (secret, message) = parse_exactly(&test_bip340_parser(), &mut input[0].clone()).await;
+    let mut key = match Secp256k1Key::from_secret(&secret) {
+        Ok(key) => key,
+        Err(_) => reject(SyscallError::InvalidParameter as u16).await,
+    };
+    let mut rv = ArrayVec::<u8, 96>::new();
+    match (key.public_key(), key.sign(&message)) {
+        (Ok(public), Ok(sig)) => {
+            let _ = rv.try_extend_from_slice(&public);
+            let _ = rv.try_extend_from_slice(&sig);
+        }
+        _ => reject::<()>(SyscallError::Unspecified as u16).await,
+    }
+    io.result_final(&rv).await;
+}
+
+// A seed of up to 64 bytes, and a path to derive from it.
+pub type TestSlip10Schema = (DArray<Byte, Byte, 64>, Bip32Key);
+
+pub type TestSlip10ParserT<BS: Readable> = impl AsyncParser<TestSlip10Schema, BS>
+    + HasOutput<TestSlip10Schema, Output = (ArrayVec<u8, 64>, ArrayVec<u32, 10>)>;
+const fn test_slip10_parser<BS: Readable>() -> TestSlip10ParserT<BS> {
+    (SubInterp(DefaultInterp), SubInterp(DefaultInterp))
+}
+
+/// Returns the SLIP-10 ed25519 public key and chain code at a path, from a seed the host gives,
+/// so that the ts-tests can check the OS's SLIP-10 derivation against SLIP-10's test vectors.
+pub async fn test_slip10(io: HostIO) {
+    let input = io.get_params::<1>().unwrap();
+    let (seed, path) = parse_exactly(&test_slip10_parser(), &mut input[0].clone()).await;
+    let mut rv = ArrayVec::<u8, 64>::new();
+    match slip10_with_seed(&seed, &path) {
+        Ok((public, chain_code)) => {
+            let _ = rv.try_extend_from_slice(&public);
+            let _ = rv.try_extend_from_slice(&chain_code);
+        }
+        Err(_) => reject::<()>(SyscallError::InvalidParameter as u16).await,
+    }
+    io.result_final(&rv).await;
+}
@@ -1,5 +1,37 @@
+use crate::interface::AppStatusWords;
+use alamgu_async_block::*;
+use arrayvec::ArrayString;
+use arrayvec::ArrayVec;
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use core::cell::Cell;
+use core::fmt::Write;
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use ledger_device_sdk::buttons::{ButtonEvent, ButtonsState};
+use ledger_device_sdk::io::StatusWords;
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use ledger_device_sdk::ui::bagls::{Displayable, LEFT_ARROW, RIGHT_ARROW};
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use ledger_device_sdk::ui::gadgets::{clear_screen, get_event};
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use ledger_device_sdk::ui::layout::{Layout, Location, StringPlace};
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+use ledger_device_sdk::ui::screen_util::screen_update;
+#[cfg(feature = "speculos")]
+use ledger_log::info;
+use ledger_parser_combinators::async_parser::*;
+use ledger_parser_combinators::interp::*;
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
 use ledger_prompts_ui::{PromptWrite, ScrollerError};
 
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+use crate::home::APP_ICON_GLYPH;
+/// Prompt text is rendered to a buffer before NBGL displays it, so the only failure is running
+/// out of room.
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+pub use core::fmt::Error as ScrollerError;
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+use ledger_device_sdk::nbgl::{Field, NbglChoice, NbglReview, NbglReviewStatus};
+
 // A couple type ascription functions to help the compiler along.
 pub const fn mkfn<A, B, C>(q: fn(&A, &mut B) -> C) -> fn(&A, &mut B) -> C {
     q
@@ -13,40 +45,671 @@ q
 }
 */
 
-#[cfg(not(target_os = "nanos"))]
+#[cfg(not(any(target_os = "nanos", target_os = "stax", target_os = "flex")))]
 #[inline(never)]
-pub fn scroller<F: for<'b> Fn(&mut PromptWrite<'b, 16>) -> Result<(), ScrollerError>>(
+pub fn scroller<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
     title: &str,
     prompt_function: F,
 ) -> Option<()> {
-    ledger_prompts_ui::write_scroller_three_rows(false, title, prompt_function)
+    let prompt_function = log_first_pass("scroller", title, &prompt_function);
+    ledger_prompts_ui::write_scroller_three_rows(false, title, |w| prompt_function(w))
 }
 
 #[cfg(target_os = "nanos")]
 #[inline(never)]
-pub fn scroller<F: for<'b> Fn(&mut PromptWrite<'b, 16>) -> Result<(), ScrollerError>>(
+pub fn scroller<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
     title: &str,
     prompt_function: F,
 ) -> Option<()> {
-    ledger_prompts_ui::write_scroller(false, title, prompt_function)
+    let prompt_function = log_first_pass("scroller", title, &prompt_function);
+    ledger_prompts_ui::write_scroller(false, title, |w| prompt_function(w))
 }
 
-#[cfg(not(target_os = "nanos"))]
+#[cfg(not(any(target_os = "nanos", target_os = "stax", target_os = "flex")))]
 #[inline(never)]
-pub fn scroller_paginated<F: for<'b> Fn(&mut PromptWrite<'b, 16>) -> Result<(), ScrollerError>>(
+pub fn scroller_paginated<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
     title: &str,
     prompt_function: F,
 ) -> Option<()> {
-    ledger_prompts_ui::write_scroller_three_rows(true, title, prompt_function)
+    let prompt_function = log_first_pass("scroller_paginated", title, &prompt_function);
+    ledger_prompts_ui::write_scroller_three_rows(true, title, |w| prompt_function(w))
 }
 
 #[cfg(target_os = "nanos")]
 #[inline(never)]
-pub fn scroller_paginated<F: for<'b> Fn(&mut PromptWrite<'b, 16>) -> Result<(), ScrollerError>>(
+pub fn scroller_paginated<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
+    title: &str,
+    prompt_function: F,
+) -> Option<()> {
+    let prompt_function = log_first_pass("scroller_paginated", title, &prompt_function);
+    ledger_prompts_ui::write_scroller(true, title, |w| prompt_function(w))
+}
+
+// NBGL lays out and pages long text itself, so on Stax and Flex every prompt is a single
+// screen with the whole text, and paginated and non-paginated scrollers look the same.
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+#[inline(never)]
+pub fn scroller<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
+    title: &str,
+    prompt_function: F,
+) -> Option<()> {
+    nbgl_scroller("scroller", title, &prompt_function)
+}
+
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+#[inline(never)]
+pub fn scroller_paginated<F: Fn(&mut dyn Write) -> Result<(), ScrollerError>>(
     title: &str,
     prompt_function: F,
 ) -> Option<()> {
-    ledger_prompts_ui::write_scroller(true, title, prompt_function)
+    nbgl_scroller("scroller_paginated", title, &prompt_function)
+}
+
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+fn nbgl_scroller(
+    kind: &str,
+    title: &str,
+    prompt_function: &dyn Fn(&mut dyn Write) -> Result<(), ScrollerError>,
+) -> Option<()> {
+    let mut text = ArrayString::<512>::new();
+    log_prompt(kind, title, prompt_function, &mut text).ok()?;
+    NbglChoice::new()
+        .glyph(&APP_ICON_GLYPH)
+        .show(title, &text, "Continue", "Reject")
+        .then_some(())
+}
+
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+#[inline(never)]
+pub fn final_accept_prompt(prompt: &[&str]) -> Option<()> {
+    log_text("final_accept_prompt", "", prompt);
+    ledger_prompts_ui::final_accept_prompt(prompt)
+}
+
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+#[inline(never)]
+pub fn final_accept_prompt(prompt: &[&str]) -> Option<()> {
+    log_text("final_accept_prompt", "", prompt);
+    NbglChoice::new()
+        .glyph(&APP_ICON_GLYPH)
+        .show(
+            prompt.first().copied().unwrap_or("Confirm?"),
+            "",
+            "Confirm",
+            "Reject",
+        )
+        .then_some(())
+}
+
+/// Asks `question`, answered with `yes` or `no`. On the Nano models the question and each
+/// answer are a screen of their own, and both buttons on an answer choose it.
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+#[inline(never)]
+pub fn choice(question: &str, yes: &str, no: &str) -> bool {
+    let screens = [question, yes, no];
+    let mut buttons = ButtonsState::new();
+    let mut screen = 0;
+    loop {
+        log_text("choice", "", &[screens[screen]]);
+        clear_screen();
+        screens[screen].place(Location::Middle, Layout::Centered, true);
+        if screen > 0 {
+            LEFT_ARROW.display();
+        }
+        if screen + 1 < screens.len() {
+            RIGHT_ARROW.display();
+        }
+        screen_update();
+        loop {
+            match get_event(&mut buttons) {
+                Some(ButtonEvent::LeftButtonRelease) if screen > 0 => screen -= 1,
+                Some(ButtonEvent::RightButtonRelease) if screen + 1 < screens.len() => screen += 1,
+                Some(ButtonEvent::BothButtonsRelease) if screen > 0 => return screen == 1,
+                _ => continue,
+            }
+            break;
+        }
+    }
+}
+
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+#[inline(never)]
+pub fn choice(question: &str, yes: &str, no: &str) -> bool {
+    log_text("choice", "", &[question]);
+    NbglChoice::new()
+        .glyph(&APP_ICON_GLYPH)
+        .show(question, "", yes, no)
+}
+
+/// Renders a prompt by running `prompt_function` on `w`. Under speculos, also writes the prompt
+/// to the log as a `PROMPT` record giving its kind and title, then its full text as
+/// `PROMPT_BODY` chunks, then `PROMPT_END`. Strings are quoted with `{:?}`. Test harnesses join
+/// the chunks to recover the text without OCR, and without depending on how a given device
+/// model paginates it. The text logged is what the same run wrote to `w`.
+#[cfg(feature = "speculos")]
+#[inline(never)]
+fn log_prompt(
+    kind: &str,
+    title: &str,
+    prompt_function: &dyn Fn(&mut dyn Write) -> Result<(), ScrollerError>,
+    w: &mut dyn Write,
+) -> Result<(), ScrollerError> {
+    info!("PROMPT {{\"kind\": {:?}, \"title\": {:?}}}", kind, title);
+    let mut body = PromptLogWrite {
+        out: w,
+        chunk: ArrayString::new(),
+    };
+    let rv = prompt_function(&mut body);
+    body.flush();
+    info!("PROMPT_END");
+    rv
+}
+
+#[cfg(not(feature = "speculos"))]
+#[inline(always)]
+fn log_prompt(
+    _kind: &str,
+    _title: &str,
+    prompt_function: &dyn Fn(&mut dyn Write) -> Result<(), ScrollerError>,
+    w: &mut dyn Write,
+) -> Result<(), ScrollerError> {
+    prompt_function(w)
+}
+
+/// Logs a prompt whose text is already at hand, as `log_prompt` does.
+#[cfg(feature = "speculos")]
+#[inline(never)]
+fn log_text(kind: &str, title: &str, text: &[&str]) {
+    let _ = log_prompt(
+        kind,
+        title,
+        &|w| {
+            for part in text {
+                w.write_str(part)?;
+            }
+            Ok(())
+        },
+        &mut Discard,
+    );
+}
+
+#[cfg(not(feature = "speculos"))]
+#[inline(always)]
+fn log_text(_kind: &str, _title: &str, _text: &[&str]) {}
+
+/// `prompt_function`, logging the prompt the first time it runs. The Nano scrollers run it again
+/// for every page they draw.
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+fn log_first_pass<'a>(
+    kind: &'a str,
+    title: &'a str,
+    prompt_function: &'a dyn Fn(&mut dyn Write) -> Result<(), ScrollerError>,
+) -> impl Fn(&mut dyn Write) -> Result<(), ScrollerError> + 'a {
+    let logged = Cell::new(false);
+    move |w| {
+        if logged.replace(true) {
+            prompt_function(w)
+        } else {
+            log_prompt(kind, title, prompt_function, w)
+        }
+    }
+}
+
+/// Passes writes on to `out`, and logs them as `PROMPT_BODY` chunks.
+#[cfg(feature = "speculos")]
+struct PromptLogWrite<'a> {
+    out: &'a mut dyn Write,
+    chunk: ArrayString<32>,
+}
+
+#[cfg(feature = "speculos")]
+impl PromptLogWrite<'_> {
+    fn flush(&mut self) {
+        if !self.chunk.is_empty() {
+            info!("PROMPT_BODY {:?}", self.chunk.as_str());
+            self.chunk.clear();
+        }
+    }
+}
+
+#[cfg(feature = "speculos")]
+impl Write for PromptLogWrite<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.chunk.try_push(c).is_err() {
+                self.flush();
+                self.chunk.push(c);
+            }
+        }
+        self.out.write_str(s)
+    }
+}
+
+/// Where `log_text` renders text that is only logged.
+#[cfg(feature = "speculos")]
+struct Discard;
+
+#[cfg(feature = "speculos")]
+impl Write for Discard {
+    fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+        Ok(())
+    }
+}
+
+/// Rejects with SW_TRAILING_DATA unless `input` has been read to the end. A parameter with bytes
+/// after what the app parsed could mean one thing to the app and another to a host.
+pub async fn expect_end(input: &mut ByteStream) {
+    if !input.is_empty().await {
+        reject::<()>(AppStatusWords::TrailingData as u16).await;
+    }
+}
+
+/// Parses the whole of a block protocol parameter; handlers parse every parameter with this, or
+/// call `expect_end` themselves once they have read one in pieces.
+pub async fn parse_exactly<S, P: AsyncParser<S, ByteStream>>(
+    parser: &P,
+    input: &mut ByteStream,
+) -> <P as HasOutput<S>>::Output {
+    let rv = parser.parse(input).await;
+    expect_end(input).await;
+    rv
+}
+
+/// Writes bytes as lowercase hex.
+pub fn write_hex(w: &mut dyn Write, bytes: &[u8]) -> Result<(), ScrollerError> {
+    for byte in bytes {
+        write!(w, "{byte:02x}")?;
+    }
+    Ok(())
+}
+
+/// Upper bound on the fields in one review, so that NBGL can render them all up front. The
+/// largest review, of a transfer from four accounts in expert mode, has exactly this many.
+pub const MAX_REVIEW_FIELDS: usize = 12;
+
+/// Writes the value of one review field.
+pub type ValueWriter<'a> = &'a dyn Fn(&mut dyn Write) -> Result<(), ScrollerError>;
+
+/// Why a review ended without the user's approval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReviewError {
+    Rejected,
+    /// A value did not fit the display buffers, or there were more warnings or fields than a
+    /// review holds, so the review could not be shown in full.
+    TooLong,
+}
+
+impl ReviewError {
+    /// The status word to refuse the request with.
+    pub fn status_word(self) -> u16 {
+        match self {
+            ReviewError::Rejected => StatusWords::UserCancelled as u16,
+            ReviewError::TooLong => AppStatusWords::ReviewTooLong as u16,
+        }
+    }
+}
+
+/// The status word to refuse a request with when it failed around a review: the review's own
+/// error if it did not end in approval, and a rejection by the user otherwise.
+pub fn refusal(reviewed: Result<(), ReviewError>) -> u16 {
+    reviewed
+        .err()
+        .unwrap_or(ReviewError::Rejected)
+        .status_word()
+}
+
+/// A review flow: an optional banner and intro screen, warnings, (title, value) fields and the label of the
+/// final approval. Handlers describe what to show, and the renderer for the device family
+/// decides pagination, layout and how the user navigates and approves.
+#[derive(Default)]
+pub struct Review<'a> {
+    banner: Option<&'a str>,
+    intro: Option<&'a str>,
+    warnings: ArrayVec<&'a str, 2>,
+    fields: ArrayVec<(&'a str, ValueWriter<'a>), MAX_REVIEW_FIELDS>,
+    action: Option<&'a str>,
+    /// Set when a warning or field did not fit, so that the review is refused rather than shown
+    /// without it.
+    incomplete: bool,
+}
+
+impl<'a> Review<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Text shown before anything else, such as the network when it is not mainnet.
+    pub fn banner(mut self, text: &'a str) -> Self {
+        self.banner = Some(text);
+        self
+    }
+
+    pub fn intro(mut self, title: &'a str) -> Self {
+        self.intro = Some(title);
+        self
+    }
+
+    pub fn warning(mut self, text: &'a str) -> Self {
+        self.incomplete |= self.warnings.try_push(text).is_err();
+        self
+    }
+
+    pub fn field(mut self, title: &'a str, value: ValueWriter<'a>) -> Self {
+        self.incomplete |= self.fields.try_push((title, value)).is_err();
+        self
+    }
+
+    pub fn action(mut self, label: &'a str) -> Self {
+        self.action = Some(label);
+        self
+    }
+}
+
+// Buttons: every banner, intro, warning and field page is one screen in a single sequence, followed by
+// the action, "Confirm" and "Reject". Left and right move through the whole sequence, both
+// buttons on any content screen jump to "Reject", and only "Confirm" or "Reject" end the review.
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+impl Review<'_> {
+    #[inline(never)]
+    pub fn show(&self) -> Result<(), ReviewError> {
+        if self.incomplete {
+            return Err(ReviewError::TooLong);
+        }
+        let mut buttons = ButtonsState::new();
+        let mut screen = self.first_screen();
+        // Entries are logged as they are first drawn, in order, and then the action.
+        let mut logged = 0;
+        // Screens only fail to draw or paginate when a title or value does not fit.
+        loop {
+            self.draw(screen, &mut logged)
+                .map_err(|_| ReviewError::TooLong)?;
+            screen = match get_event(&mut buttons) {
+                Some(ButtonEvent::LeftButtonRelease) => self.previous_screen(screen),
+                Some(ButtonEvent::RightButtonRelease) => self.next_screen(screen),
+                Some(ButtonEvent::BothButtonsRelease) => match screen {
+                    ReviewScreen::Confirm => return Ok(()),
+                    ReviewScreen::Reject => return Err(ReviewError::Rejected),
+                    _ => Some(ReviewScreen::Reject),
+                },
+                _ => continue,
+            }
+            .ok_or(ReviewError::TooLong)?;
+        }
+    }
+
+    fn entry_count(&self) -> usize {
+        self.banner.iter().count()
+            + self.intro.iter().count()
+            + self.warnings.len()
+            + self.fields.len()
+    }
+
+    /// Title and value of the `index`th entry, counting the banner, intro, warnings and fields
+    /// in order.
+    fn entry(&self, index: usize) -> Option<(&str, ReviewValue)> {
+        let mut index = index;
+        if let Some(banner) = self.banner {
+            if index == 0 {
+                return Some((banner, ReviewValue::Empty));
+            }
+            index -= 1;
+        }
+        if let Some(intro) = self.intro {
+            if index == 0 {
+                return Some((intro, ReviewValue::Empty));
+            }
+            index -= 1;
+        }
+        if let Some(warning) = self.warnings.get(index) {
+            return Some(("WARNING", ReviewValue::Text(warning)));
+        }
+        let (title, value) = self.fields.get(index - self.warnings.len())?;
+        Some((title, ReviewValue::Writer(*value)))
+    }
+
+    fn page_count(&self, index: usize) -> Option<usize> {
+        let (_, value) = self.entry(index)?;
+        Some(review_page(&value, 0, None).ok()?.1)
+    }
+
+    fn first_screen(&self) -> ReviewScreen {
+        match (self.entry_count(), self.action) {
+            (0, Some(_)) => ReviewScreen::Action,
+            (0, None) => ReviewScreen::Confirm,
+            _ => ReviewScreen::Entry(0, 0),
+        }
+    }
+
+    fn last_entry_screen(&self) -> Option<ReviewScreen> {
+        let last = self.entry_count().checked_sub(1)?;
+        Some(ReviewScreen::Entry(last, self.page_count(last)? - 1))
+    }
+
+    fn next_screen(&self, screen: ReviewScreen) -> Option<ReviewScreen> {
+        Some(match screen {
+            ReviewScreen::Entry(index, page) if page + 1 < self.page_count(index)? => {
+                ReviewScreen::Entry(index, page + 1)
+            }
+            ReviewScreen::Entry(index, _) if index + 1 < self.entry_count() => {
+                ReviewScreen::Entry(index + 1, 0)
+            }
+            ReviewScreen::Entry(..) if self.action.is_some() => ReviewScreen::Action,
+            ReviewScreen::Entry(..) | ReviewScreen::Action => ReviewScreen::Confirm,
+            ReviewScreen::Confirm | ReviewScreen::Reject => ReviewScreen::Reject,
+        })
+    }
+
+    fn previous_screen(&self, screen: ReviewScreen) -> Option<ReviewScreen> {
+        Some(match screen {
+            ReviewScreen::Entry(index, page) if page > 0 => ReviewScreen::Entry(index, page - 1),
+            ReviewScreen::Entry(index, _) if index > 0 => {
+                ReviewScreen::Entry(index - 1, self.page_count(index - 1)? - 1)
+            }
+            ReviewScreen::Confirm if self.action.is_some() => ReviewScreen::Action,
+            ReviewScreen::Action | ReviewScreen::Confirm => {
+                self.last_entry_screen().unwrap_or(screen)
+            }
+            ReviewScreen::Reject => ReviewScreen::Confirm,
+            ReviewScreen::Entry(..) => screen,
+        })
+    }
+
+    fn draw(&self, screen: ReviewScreen, logged: &mut usize) -> Result<(), ScrollerError> {
+        let closing = matches!(screen, ReviewScreen::Action | ReviewScreen::Confirm);
+        if closing && *logged == self.entry_count() {
+            *logged += 1;
+            log_text(
+                "final_accept_prompt",
+                "",
+                &[self.action.unwrap_or_default()],
+            );
+        }
+        clear_screen();
+        match screen {
+            ReviewScreen::Entry(index, page) => {
+                let (title, value) = self.entry(index).ok_or(core::fmt::Error)?;
+                let log_title = if (index, page) == (*logged, 0) {
+                    *logged += 1;
+                    Some(title)
+                } else {
+                    None
+                };
+                let (text, pages) = review_page(&value, page, log_title)?;
+                let mut header: ArrayString<64> = ArrayString::new();
+                if pages > 1 {
+                    write!(header, "{} ({}/{})", title, page + 1, pages)?;
+                } else {
+                    header.try_push_str(title).map_err(|_| core::fmt::Error)?;
+                }
+                header.as_str().place(Location::Top, Layout::Centered, true);
+                for (i, y) in REVIEW_LINE_ROWS.into_iter().enumerate() {
+                    let start = (i * REVIEW_LINE_CHARS).min(text.len());
+                    let end = (start + REVIEW_LINE_CHARS).min(text.len());
+                    text.get(start..end).unwrap_or_default().place(
+                        Location::Custom(y),
+                        Layout::Centered,
+                        false,
+                    );
+                }
+                if index > 0 || page > 0 {
+                    LEFT_ARROW.display();
+                }
+            }
+            ReviewScreen::Action => {
+                self.action
+                    .unwrap_or_default()
+                    .place(Location::Middle, Layout::Centered, false);
+                if self.entry_count() > 0 {
+                    LEFT_ARROW.display();
+                }
+            }
+            ReviewScreen::Confirm => {
+                "Confirm".place(Location::Middle, Layout::Centered, true);
+                if self.action.is_some() || self.entry_count() > 0 {
+                    LEFT_ARROW.display();
+                }
+            }
+            ReviewScreen::Reject => {
+                "Reject".place(Location::Middle, Layout::Centered, true);
+                LEFT_ARROW.display();
+            }
+        }
+        if screen != ReviewScreen::Reject {
+            RIGHT_ARROW.display();
+        }
+        screen_update();
+        Ok(())
+    }
+}
+
+/// Position in a button review: an entry and one of its pages, then the closing screens.
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReviewScreen {
+    Entry(usize, usize),
+    Action,
+    Confirm,
+    Reject,
+}
+
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+enum ReviewValue<'a> {
+    Empty,
+    Text(&'a str),
+    Writer(ValueWriter<'a>),
+}
+
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+impl ReviewValue<'_> {
+    /// The kind of prompt the value is logged as, as the scrollers would show it.
+    fn kind(&self) -> &'static str {
+        match self {
+            ReviewValue::Writer(_) => "scroller_paginated",
+            ReviewValue::Empty | ReviewValue::Text(_) => "scroller",
+        }
+    }
+
+    fn write(&self, w: &mut dyn Write) -> Result<(), ScrollerError> {
+        match self {
+            ReviewValue::Empty => Ok(()),
+            ReviewValue::Text(text) => Ok(w.write_str(text)?),
+            ReviewValue::Writer(value) => value(w),
+        }
+    }
+}
+
+// Value rows sit where the scrollers put theirs, so the screens read (and OCR) the same.
+#[cfg(target_os = "nanos")]
+const REVIEW_LINES: usize = 1;
+#[cfg(target_os = "nanos")]
+const REVIEW_LINE_ROWS: [usize; REVIEW_LINES] = [16];
+#[cfg(not(any(target_os = "nanos", target_os = "stax", target_os = "flex")))]
+const REVIEW_LINES: usize = 3;
+#[cfg(not(any(target_os = "nanos", target_os = "stax", target_os = "flex")))]
+const REVIEW_LINE_ROWS: [usize; REVIEW_LINES] = [16, 31, 46];
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+const REVIEW_LINE_CHARS: usize = 16;
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+const REVIEW_PAGE_CHARS: usize = REVIEW_LINES * REVIEW_LINE_CHARS;
+
+/// Page `page` of a value, paginated as the scrollers do, and the number of pages it takes. With
+/// `log_title`, the value is also logged under that title.
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
+fn review_page(
+    value: &ReviewValue,
+    page: usize,
+    log_title: Option<&str>,
+) -> Result<(ArrayString<REVIEW_PAGE_CHARS>, usize), ScrollerError> {
+    let mut buffer = ArrayString::new();
+    let mut w = PromptWrite {
+        offset: page * REVIEW_PAGE_CHARS,
+        buffer: &mut buffer,
+        total: 0,
+    };
+    match log_title {
+        Some(title) => log_prompt(value.kind(), title, &|w| value.write(w), &mut w)?,
+        None => value.write(&mut w)?,
+    }
+    let pages = w.total.div_ceil(REVIEW_PAGE_CHARS).max(1);
+    Ok((buffer, pages))
+}
+
+/// Room for the text of every field of a touch review. The longest review is that of a memo,
+/// whose value alone can be `2 * MAX_MEMO_LEN` characters of hex.
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+const REVIEW_TEXT_LEN: usize = 1024;
+
+// Touch: warnings are acknowledged one by one, then every field goes into a single NBGL review
+// that does its own layout and paging and ends with the approval. The banner is the review's
+// subtitle.
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+impl Review<'_> {
+    #[inline(never)]
+    pub fn show(&self) -> Result<(), ReviewError> {
+        if self.incomplete {
+            return Err(ReviewError::TooLong);
+        }
+        for warning in &self.warnings {
+            scroller("WARNING", |w| Ok(w.write_str(warning)?)).ok_or(ReviewError::Rejected)?;
+        }
+        if let Some(banner) = self.banner {
+            log_text("scroller", banner, &[]);
+        }
+        if let Some(intro) = self.intro {
+            log_text("scroller", intro, &[]);
+        }
+        // Every value is rendered into one buffer, and each field shows its own slice of it.
+        let mut text: ArrayString<REVIEW_TEXT_LEN> = ArrayString::new();
+        let mut ends: ArrayVec<usize, MAX_REVIEW_FIELDS> = ArrayVec::new();
+        for (title, value) in &self.fields {
+            log_prompt("scroller_paginated", title, *value, &mut text)
+                .map_err(|_| ReviewError::TooLong)?;
+            ends.push(text.len());
+        }
+        let fields: ArrayVec<Field, MAX_REVIEW_FIELDS> = self
+            .fields
+            .iter()
+            .zip(ends.iter())
+            .scan(0, |start, ((title, _), &end)| {
+                let value = &text[*start..end];
+                *start = end;
+                Some(Field { name: title, value })
+            })
+            .collect();
+        let action = self.action.unwrap_or("Confirm?");
+        log_text("final_accept_prompt", "", &[action]);
+
+        let approved = NbglReview::new()
+            .glyph(&APP_ICON_GLYPH)
+            .titles(
+                self.intro.unwrap_or("Review"),
+                self.banner.unwrap_or_default(),
+                action,
+            )
+            .show(&fields);
+        NbglReviewStatus::new().show(approved);
+        approved.then_some(()).ok_or(ReviewError::Rejected)
+    }
 }
 
 use core::future::Future;
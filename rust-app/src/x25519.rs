@@ -0,0 +1,309 @@
+//! X25519 Diffie-Hellman (RFC 7748).
+//!
+//! The SDK has no Montgomery ladder for Curve25519 that takes a raw scalar, so the app does the
+//! field arithmetic itself, in constant time with respect to the scalar. Field elements are five
+//! 51 bit limbs.
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// `(A - 2) / 4` for Curve25519.
+const A24: u64 = 121_665;
+
+/// The u coordinate of the base point.
+pub const BASE_POINT: [u8; 32] = {
+    let mut u = [0; 32];
+    u[0] = 9;
+    u
+};
+
+/// An element of GF(2^255 - 19), with limbs slightly above 51 bits allowed between operations.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// Reads a little endian u coordinate, ignoring the top bit as RFC 7748 requires.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let load = |i: usize| {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[i..i + 8]);
+            u64::from_le_bytes(word)
+        };
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// The canonical little endian encoding.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().carry().0;
+        // h is now below 2p; subtract p if h + 19 carries out of 255 bits.
+        let mut q = (h[0] + 19) >> 51;
+        for limb in &h[1..] {
+            q = (limb + q) >> 51;
+        }
+        h[0] += 19 * q;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 51;
+            h[i] &= MASK;
+        }
+        h[4] &= MASK;
+
+        let mut out = [0; 32];
+        let mut acc: u128 = 0;
+        let mut bits = 0;
+        let mut i = 0;
+        for limb in h {
+            acc |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                out[i] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                i += 1;
+            }
+        }
+        out[i] = acc as u8;
+        out
+    }
+
+    /// Brings every limb back to about 51 bits.
+    fn carry(self) -> Fe {
+        let mut l = self.0;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[0] += (l[4] >> 51) * 19;
+        l[4] &= MASK;
+        Fe(l)
+    }
+
+    fn add(self, rhs: Fe) -> Fe {
+        let mut l = self.0;
+        for (a, b) in l.iter_mut().zip(rhs.0) {
+            *a += b;
+        }
+        Fe(l).carry()
+    }
+
+    fn sub(self, rhs: Fe) -> Fe {
+        // Add 2p first so that no limb goes negative.
+        const TWO_P: [u64; 5] = [
+            0xf_ffff_ffff_ffda,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+            0xf_ffff_ffff_fffe,
+        ];
+        let mut l = self.0;
+        for ((a, b), p) in l.iter_mut().zip(rhs.0).zip(TWO_P) {
+            *a = *a + p - b;
+        }
+        Fe(l).carry()
+    }
+
+    fn mul(self, rhs: Fe) -> Fe {
+        let a = self.0.map(|x| x as u128);
+        let b = rhs.0.map(|x| x as u128);
+        // Limbs past the fifth wrap around multiplied by 19, since 2^255 = 19.
+        let b19 = rhs.0.map(|x| (x * 19) as u128);
+        let r = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        Fe::carry_wide(r)
+    }
+
+    fn mul_small(self, k: u64) -> Fe {
+        Fe::carry_wide(self.0.map(|x| x as u128 * k as u128))
+    }
+
+    fn carry_wide(mut r: [u128; 5]) -> Fe {
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= MASK as u128;
+        }
+        r[0] += (r[4] >> 51) * 19;
+        r[4] &= MASK as u128;
+        Fe(r.map(|x| x as u64)).carry()
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// `self^(p - 2)`, which is the inverse for non-zero elements and zero for zero.
+    fn invert(self) -> Fe {
+        // p - 2 = 2^255 - 21, little endian.
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xeb;
+        exponent[31] = 0x7f;
+        let mut result = Fe::ONE;
+        for bit in (0..255).rev() {
+            result = result.square();
+            if (exponent[bit / 8] >> (bit % 8)) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    /// Swaps `a` and `b` if `swap` is 1, without branching on it.
+    fn conditional_swap(a: &mut Fe, b: &mut Fe, swap: u64) {
+        let mask = 0u64.wrapping_sub(swap);
+        for (x, y) in a.0.iter_mut().zip(b.0.iter_mut()) {
+            let t = mask & (*x ^ *y);
+            *x ^= t;
+            *y ^= t;
+        }
+    }
+}
+
+/// Clears the bits RFC 7748 requires clear, and sets the top one.
+pub fn clamp(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+    k
+}
+
+/// The X25519 function: `scalar` (clamped here) times the point with u coordinate `u`.
+pub fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let k = clamp(scalar);
+    let x1 = Fe::from_bytes(u);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swap = 0;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swap ^= bit;
+        Fe::conditional_swap(&mut x2, &mut x3, swap);
+        Fe::conditional_swap(&mut z2, &mut z3, swap);
+        swap = bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(A24)));
+    }
+    Fe::conditional_swap(&mut x2, &mut x3, swap);
+    Fe::conditional_swap(&mut z2, &mut z3, swap);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+#[cfg(all(test, not(target_family = "bolos")))]
+mod tests {
+    extern crate std;
+    use super::*;
+
+    fn unhex(s: &str) -> [u8; 32] {
+        let mut out = [0; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn rfc7748_vectors() {
+        // Section 5.2.
+        assert_eq!(
+            x25519(
+                &unhex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+                &unhex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c"),
+            ),
+            unhex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+        // The top bit of this u coordinate is set, and must be ignored.
+        assert_eq!(
+            x25519(
+                &unhex("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d"),
+                &unhex("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493"),
+            ),
+            unhex("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957")
+        );
+    }
+
+    #[test]
+    fn rfc7748_iterations() {
+        let mut k = BASE_POINT;
+        let mut u = BASE_POINT;
+        for i in 1..=1000 {
+            let next = x25519(&k, &u);
+            u = k;
+            k = next;
+            if i == 1 {
+                assert_eq!(
+                    k,
+                    unhex("422c8e7a6227d7bca1350b3e2bb7279f7897b87bb6854b783c60e80311ae3079")
+                );
+            }
+        }
+        assert_eq!(
+            k,
+            unhex("684cf59ba83309552800ef566f2f4d3c1c3887c49360e3875f2eb94d99532c51")
+        );
+    }
+
+    #[test]
+    fn rfc7748_diffie_hellman() {
+        // Section 6.1.
+        let alice = unhex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = unhex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_public = x25519(&alice, &BASE_POINT);
+        let bob_public = x25519(&bob, &BASE_POINT);
+        assert_eq!(
+            alice_public,
+            unhex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            bob_public,
+            unhex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+        let shared = unhex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&alice, &bob_public), shared);
+        assert_eq!(x25519(&bob, &alice_public), shared);
+    }
+
+    #[test]
+    fn low_order_points_give_zero() {
+        // u = 0 and u = 1 are of small order, so every scalar maps them to zero.
+        let scalar = unhex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        assert_eq!(x25519(&scalar, &[0; 32]), [0; 32]);
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(x25519(&scalar, &one), [0; 32]);
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        // p itself and p + 1 read as 0 and 1.
+        let mut p = [0xff; 32];
+        p[0] = 0xed;
+        p[31] = 0x7f;
+        assert_eq!(Fe::from_bytes(&p).to_bytes(), [0; 32]);
+        p[0] = 0xee;
+        let mut one = [0; 32];
+        one[0] = 1;
+        assert_eq!(Fe::from_bytes(&p).to_bytes(), one);
+    }
+}
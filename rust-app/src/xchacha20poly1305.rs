@@ -0,0 +1,404 @@
+//! XChaCha20-Poly1305 authenticated encryption (RFC 8439, with the extended nonce of
+//! draft-irtf-cfrg-xchacha).
+//!
+//! Used to read memos encrypted to an account. The tag is checked in constant time before
+//! anything is decrypted.
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 24;
+pub const TAG_LEN: usize = 16;
+
+/// The tag did not match: wrong key, or modified ciphertext or associated data.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AuthenticationError;
+
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// The ChaCha20 state for `key` and the last four words, before the rounds.
+fn initial_state(key: &[u8; KEY_LEN], last: [u32; 4]) -> [u32; 16] {
+    let mut s = [0; 16];
+    s[..4].copy_from_slice(&SIGMA);
+    for (word, bytes) in s[4..12].iter_mut().zip(key.chunks(4)) {
+        *word = le32(bytes);
+    }
+    s[12..].copy_from_slice(&last);
+    s
+}
+
+fn rounds(s: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(s, 0, 4, 8, 12);
+        quarter_round(s, 1, 5, 9, 13);
+        quarter_round(s, 2, 6, 10, 14);
+        quarter_round(s, 3, 7, 11, 15);
+        quarter_round(s, 0, 5, 10, 15);
+        quarter_round(s, 1, 6, 11, 12);
+        quarter_round(s, 2, 7, 8, 13);
+        quarter_round(s, 3, 4, 9, 14);
+    }
+}
+
+/// One 64 byte block of ChaCha20 keystream, with the 96 bit nonce of RFC 8439.
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let initial = initial_state(
+        key,
+        [
+            counter,
+            le32(&nonce[0..]),
+            le32(&nonce[4..]),
+            le32(&nonce[8..]),
+        ],
+    );
+    let mut s = initial;
+    rounds(&mut s);
+    let mut out = [0; 64];
+    for ((bytes, word), init) in out.chunks_mut(4).zip(s).zip(initial) {
+        bytes.copy_from_slice(&word.wrapping_add(init).to_le_bytes());
+    }
+    out
+}
+
+/// XORs `data` with the ChaCha20 keystream, starting at block `counter`.
+fn chacha20_xor(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (b, k) in chunk.iter_mut().zip(block) {
+            *b ^= k;
+        }
+    }
+}
+
+/// HChaCha20: derives a subkey from `key` and the first 16 bytes of an extended nonce.
+fn hchacha20(key: &[u8; KEY_LEN], nonce: &[u8; 16]) -> [u8; KEY_LEN] {
+    let mut s = initial_state(
+        key,
+        [
+            le32(&nonce[0..]),
+            le32(&nonce[4..]),
+            le32(&nonce[8..]),
+            le32(&nonce[12..]),
+        ],
+    );
+    rounds(&mut s);
+    let mut out = [0; KEY_LEN];
+    for (bytes, word) in out.chunks_mut(4).zip(s[..4].iter().chain(&s[12..])) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+/// Poly1305 in 26 bit limbs, so that products fit in a u64.
+struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+}
+
+const MASK26: u32 = (1 << 26) - 1;
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Poly1305 {
+        // r is clamped as the RFC requires while it is split into limbs.
+        Poly1305 {
+            r: [
+                le32(&key[0..]) & 0x3ff_ffff,
+                (le32(&key[3..]) >> 2) & 0x3ff_ff03,
+                (le32(&key[6..]) >> 4) & 0x3ff_c0ff,
+                (le32(&key[9..]) >> 6) & 0x3f0_3fff,
+                (le32(&key[12..]) >> 8) & 0x00f_ffff,
+            ],
+            h: [0; 5],
+            pad: [
+                le32(&key[16..]),
+                le32(&key[20..]),
+                le32(&key[24..]),
+                le32(&key[28..]),
+            ],
+        }
+    }
+
+    /// Adds a 16 byte block, with its high bit set unless it is a padded final block, and
+    /// multiplies by r.
+    fn block(&mut self, m: &[u8; 16], high_bit: u32) {
+        let [r0, r1, r2, r3, r4] = self.r.map(|x| x as u64);
+        let [s1, s2, s3, s4] = [r1 * 5, r2 * 5, r3 * 5, r4 * 5];
+        let h = &mut self.h;
+        h[0] += le32(&m[0..]) & MASK26;
+        h[1] += (le32(&m[3..]) >> 2) & MASK26;
+        h[2] += (le32(&m[6..]) >> 4) & MASK26;
+        h[3] += (le32(&m[9..]) >> 6) & MASK26;
+        h[4] += (le32(&m[12..]) >> 8) | high_bit;
+        let [h0, h1, h2, h3, h4] = h.map(|x| x as u64);
+
+        let d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+        let mut carry = 0;
+        for (limb, wide) in h.iter_mut().zip(d) {
+            let wide = wide + carry;
+            *limb = wide as u32 & MASK26;
+            carry = wide >> 26;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK26;
+    }
+
+    /// Feeds `data`, zero padded to a multiple of 16 bytes as the AEAD construction does.
+    fn update_padded(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut m = [0; 16];
+            m[..chunk.len()].copy_from_slice(chunk);
+            self.block(&m, 1 << 24);
+        }
+    }
+
+    fn finish(mut self) -> [u8; TAG_LEN] {
+        let h = &mut self.h;
+        for i in 1..5 {
+            h[i] += h[i - 1] >> 26;
+            h[i - 1] &= MASK26;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= MASK26;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK26;
+
+        // g = h + 5 - 2^130; use it if it did not go negative, that is if h >= p.
+        let mut g = [0; 5];
+        let mut carry = 5;
+        for (gi, hi) in g.iter_mut().zip(&h[..4]) {
+            let sum = hi + carry;
+            *gi = sum & MASK26;
+            carry = sum >> 26;
+        }
+        g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+        let use_g = (g[4] >> 31).wrapping_sub(1);
+        for (hi, gi) in h.iter_mut().zip(g) {
+            *hi = (*hi & !use_g) | (gi & use_g);
+        }
+
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0; TAG_LEN];
+        let mut carry = 0u64;
+        for ((bytes, word), pad) in tag.chunks_mut(4).zip(words).zip(self.pad) {
+            let sum = word as u64 + pad as u64 + carry;
+            bytes.copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+/// The subkey and 96 bit nonce XChaCha20 uses for `nonce`.
+fn subkey_and_nonce(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> ([u8; KEY_LEN], [u8; 12]) {
+    let mut prefix = [0; 16];
+    prefix.copy_from_slice(&nonce[..16]);
+    let mut short = [0; 12];
+    short[4..].copy_from_slice(&nonce[16..]);
+    (hchacha20(key, &prefix), short)
+}
+
+fn tag(key: &[u8; KEY_LEN], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+    let block = chacha20_block(key, 0, nonce);
+    let mut poly_key = [0; 32];
+    poly_key.copy_from_slice(&block[..32]);
+    let mut poly = Poly1305::new(&poly_key);
+    poly.update_padded(aad);
+    poly.update_padded(ciphertext);
+    let mut lengths = [0; 16];
+    lengths[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly.block(&lengths, 1 << 24);
+    poly.finish()
+}
+
+/// Encrypts `data` in place and returns its tag.
+pub fn encrypt(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    data: &mut [u8],
+) -> [u8; TAG_LEN] {
+    let (subkey, nonce) = subkey_and_nonce(key, nonce);
+    chacha20_xor(&subkey, 1, &nonce, data);
+    tag(&subkey, &nonce, aad, data)
+}
+
+/// Checks `expected_tag` and, if it matches, decrypts `data` in place. On failure `data` is left
+/// as it was.
+pub fn decrypt(
+    key: &[u8; KEY_LEN],
+    nonce: &[u8; NONCE_LEN],
+    aad: &[u8],
+    data: &mut [u8],
+    expected_tag: &[u8; TAG_LEN],
+) -> Result<(), AuthenticationError> {
+    let (subkey, nonce) = subkey_and_nonce(key, nonce);
+    let actual = tag(&subkey, &nonce, aad, data);
+    let difference = actual
+        .iter()
+        .zip(expected_tag)
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    if difference != 0 {
+        return Err(AuthenticationError);
+    }
+    chacha20_xor(&subkey, 1, &nonce, data);
+    Ok(())
+}
+
+#[cfg(all(test, not(target_family = "bolos")))]
+mod tests {
+    extern crate std;
+    use super::*;
+    use core::convert::TryInto;
+    use std::vec::Vec;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    const AAD: &str = "50515253c0c1c2c3c4c5c6c7";
+
+    fn key_80() -> [u8; KEY_LEN] {
+        core::array::from_fn(|i| 0x80 + i as u8)
+    }
+
+    #[test]
+    fn rfc8439_aead() {
+        // Section 2.8.2, which uses the 96 bit nonce directly.
+        let nonce: [u8; 12] = unhex("070000004041424344454647").try_into().unwrap();
+        let mut data = SUNSCREEN.to_vec();
+        chacha20_xor(&key_80(), 1, &nonce, &mut data);
+        assert_eq!(
+            data,
+            unhex("d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b6116")
+        );
+        assert_eq!(
+            tag(&key_80(), &nonce, &unhex(AAD), &data)[..],
+            unhex("1ae10b594f09e26a7e902ecbd0600691")
+        );
+    }
+
+    #[test]
+    fn poly1305_final_reduction() {
+        // RFC 8439 appendix A.3, test vector 6: h reaches p before the final reduction.
+        let mut key = [0; 32];
+        key[0] = 2;
+        let mut poly = Poly1305::new(&key);
+        poly.block(&[0xff; 16], 1 << 24);
+        let mut expected = [0; TAG_LEN];
+        expected[0] = 3;
+        assert_eq!(poly.finish(), expected);
+    }
+
+    #[test]
+    fn hchacha20_vector() {
+        // draft-irtf-cfrg-xchacha section 2.2.1.
+        let key = core::array::from_fn(|i| i as u8);
+        let nonce = unhex("000000090000004a0000000031415927")
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            hchacha20(&key, &nonce)[..],
+            unhex("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc")
+        );
+    }
+
+    #[test]
+    fn xchacha20poly1305_vector() {
+        // draft-irtf-cfrg-xchacha appendix A.3.1.
+        let nonce = core::array::from_fn(|i| 0x40 + i as u8);
+        let mut data = SUNSCREEN.to_vec();
+        let tag = encrypt(&key_80(), &nonce, &unhex(AAD), &mut data);
+        assert_eq!(
+            data,
+            unhex("bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff921f9664c97637da9768812f615c68b13b52e")
+        );
+        assert_eq!(tag[..], unhex("c0875924c1c7987947deafd8780acf49"));
+        assert_eq!(
+            decrypt(&key_80(), &nonce, &unhex(AAD), &mut data, &tag),
+            Ok(())
+        );
+        assert_eq!(data, SUNSCREEN);
+    }
+
+    #[test]
+    fn tags_for_every_padding() {
+        // Checked against an independent implementation, around the 16 and 64 byte boundaries.
+        for (length, expected) in [
+            (0, "360d9a282f4280b2b351ec579d919f1f"),
+            (1, "24255cc5c866d4c06fdf16256e2f80d4"),
+            (15, "1b1f38b0cf7ce9fb5de11b27ff1e13b5"),
+            (16, "1c5dae0d0b7adc26dca8ab528a7f3901"),
+            (17, "3f50ec9ce7c1dd55924e3fc00ee33bd5"),
+            (63, "929267103edfc7c8cb778aa38c304708"),
+            (64, "6ac84dd95384f8dde4579a8a0e1d595a"),
+            (65, "479684ce3bca487211e1d06ce45d2133"),
+            (256, "14249a5b1123b358244606b01cbeb78e"),
+        ] {
+            let plaintext: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let mut data = plaintext.clone();
+            let tag = encrypt(&[0x42; KEY_LEN], &[0x24; NONCE_LEN], &[], &mut data);
+            assert_eq!(tag[..], unhex(expected), "length {}", length);
+            decrypt(&[0x42; KEY_LEN], &[0x24; NONCE_LEN], &[], &mut data, &tag).unwrap();
+            assert_eq!(data, plaintext);
+        }
+    }
+
+    #[test]
+    fn rejects_modified_messages() {
+        let nonce = [0x24; NONCE_LEN];
+        let mut ciphertext = SUNSCREEN.to_vec();
+        let tag = encrypt(&key_80(), &nonce, b"aad", &mut ciphertext);
+
+        let mut data = ciphertext.clone();
+        data[10] ^= 1;
+        let tampered = data.clone();
+        assert_eq!(
+            decrypt(&key_80(), &nonce, b"aad", &mut data, &tag),
+            Err(AuthenticationError)
+        );
+        // Nothing is decrypted when the tag does not match.
+        assert_eq!(data, tampered);
+
+        let mut data = ciphertext.clone();
+        assert!(decrypt(&key_80(), &nonce, b"aaD", &mut data, &tag).is_err());
+        assert!(decrypt(&[0x42; KEY_LEN], &nonce, b"aad", &mut data, &tag).is_err());
+        let mut other_nonce = nonce;
+        other_nonce[23] ^= 1;
+        assert!(decrypt(&key_80(), &other_nonce, b"aad", &mut data, &tag).is_err());
+        let mut bad_tag = tag;
+        bad_tag[15] ^= 0x80;
+        assert!(decrypt(&key_80(), &nonce, b"aad", &mut data, &bad_tag).is_err());
+        assert_eq!(data, ciphertext);
+    }
+}
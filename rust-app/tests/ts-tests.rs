@@ -5,7 +5,10 @@
 
 use ledger_device_sdk::exit_app;
 
+#[cfg(not(any(target_os = "stax", target_os = "flex")))]
 use alamgu_example::main_nanos::*;
+#[cfg(any(target_os = "stax", target_os = "flex"))]
+use alamgu_example::main_stax::*;
 
 #[no_mangle]
 extern "C" fn sample_main() {
@@ -0,0 +1,196 @@
+import { sendCommandAndAccept, sendCommandExpectFail, sendCommandExpectStatus, BASE_URL, toggleNetworkSettings, toggleExpertModeSettings, recipient, nativeId, transfer } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import * as blake2b from "blake2b";
+import { instantiate, Nacl } from "js-nacl";
+import * as crypto from "crypto";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+// Test builds embed the public key for this seed (rust-app/asset-descriptor-test-key.hex).
+const descriptorKeySeed = crypto.createHash("sha256").update("alamgu-example asset descriptor test key").digest();
+const descriptorDomain = Buffer.from("alamgu-example asset descriptor");
+
+const path = "44'/535348'/0'";
+const address = "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488";
+const tokenId = "5e".repeat(32);
+
+interface Descriptor {
+  assetId: string,
+  ticker: string,
+  decimals: number,
+  chainId: number,
+}
+
+const descriptorFields = function(d: Descriptor): Buffer {
+  const chainId = Buffer.alloc(4);
+  chainId.writeUInt32LE(d.chainId);
+  return Buffer.concat([
+    Buffer.from(d.assetId, "hex"),
+    Buffer.from([d.ticker.length]),
+    Buffer.from(d.ticker, "ascii"),
+    Buffer.from([d.decimals]),
+    chainId,
+  ]);
+}
+
+const signedDescriptor = function(d: Descriptor): Buffer {
+  const fields = descriptorFields(d);
+  const { signSk } = nacl.crypto_sign_seed_keypair(descriptorKeySeed);
+  const sig = nacl.crypto_sign_detached(Buffer.concat([descriptorDomain, fields]), signSk);
+  return Buffer.concat([fields, Buffer.from(sig)]);
+}
+
+const provideDescriptor = async function(client: any, payload: Buffer) {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  await client.sendChunks(0x00, 0x04, 0, 0, [payload]);
+}
+
+const transferPrompts = function(amountPrompts: any[], prefix: string = "exa") {
+  return [
+    { "header": "Review Transfer", "prompt": "" },
+    ...amountPrompts,
+    { "header": "To", "prompt": prefix + ":" + recipient, "paginate": true },
+    { "header": "From", "prompt": prefix + ":" + address, "paginate": true },
+    { "text": "Sign Transaction?", "x": 19, "y": 11 },
+    { "text": "Confirm", "x": 43, "y": 11 },
+  ];
+}
+
+const signTransfer = async function(client: Common, txn: Buffer) {
+  const { publicKey } = await client.getPublicKey(path);
+  // We don't want the prompts from getPublicKey in our result
+  await Axios.delete(BASE_URL + "/events");
+
+  const sig = await client.signTransaction(path, txn);
+  const hash = blake2b(32).update(txn).digest();
+  expect(nacl.crypto_sign_verify_detached(sig.signature, hash, publicKey)).to.equal(true);
+}
+
+describe("Asset descriptor tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("shows native transfers with the native ticker", async function () {
+    await sendCommandAndAccept(async (client : Common) => {
+      await signTransfer(client, transfer({ chainId: 1, assetId: nativeId, amount: 1500000000 }));
+    }, transferPrompts([
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+    ]));
+  });
+
+  it("shows transfers of unknown assets in base units", async function () {
+    await sendCommandAndAccept(async (client : Common) => {
+      await signTransfer(client, transfer({ chainId: 1, assetId: "77".repeat(32), amount: 1234500000 }));
+    }, transferPrompts([
+      { "header": "Amount", "prompt": "1,234,500,000", "paginate": true },
+      { "header": "Asset", "prompt": "77".repeat(32), "paginate": true },
+    ]));
+  });
+
+  it("uses a provided descriptor to show token transfers", async function () {
+    await sendCommandAndAccept(async (client : Common) => {
+      await provideDescriptor(client, signedDescriptor({ assetId: tokenId, ticker: "TKN", decimals: 6, chainId: 1 }));
+      await signTransfer(client, transfer({ chainId: 1, assetId: tokenId, amount: 1234500000 }));
+    }, transferPrompts([
+      { "header": "Amount", "prompt": "1,234.5 TKN", "paginate": true },
+    ]));
+  });
+
+  it("only uses a descriptor for its own chain", async function () {
+    await sendCommandAndAccept(async (client : Common) => {
+      await provideDescriptor(client, signedDescriptor({ assetId: tokenId, ticker: "TKN", decimals: 6, chainId: 2 }));
+      await signTransfer(client, transfer({ chainId: 1, assetId: tokenId, amount: 1234500000 }));
+    }, transferPrompts([
+      { "header": "Amount", "prompt": "1,234,500,000", "paginate": true },
+      { "header": "Asset", "prompt": tokenId, "paginate": true },
+    ]));
+  });
+
+  it("rejects a descriptor with a bad signature", async function () {
+    const payload = signedDescriptor({ assetId: tokenId, ticker: "TKN", decimals: 6, chainId: 1 });
+    // Claim more decimals than were signed for.
+    payload[32 + 1 + 3] = 0;
+    await sendCommandExpectFail(async (client : Common) => {
+      await provideDescriptor(client, payload);
+    });
+  });
+
+  it("rejects a descriptor with an unprintable ticker", async function () {
+    await sendCommandExpectFail(async (client : Common) => {
+      await provideDescriptor(client, signedDescriptor({ assetId: tokenId, ticker: "T\nK", decimals: 6, chainId: 1 }));
+    });
+  });
+
+  it("rejects a descriptor followed by trailing bytes", async function () {
+    await sendCommandExpectStatus(async (client : Common) => {
+      await provideDescriptor(client, Buffer.concat([signedDescriptor({ assetId: tokenId, ticker: "TKN", decimals: 6, chainId: 1 }), Buffer.from([0])]));
+    }, 0x6A80);
+  });
+
+  it("rejects transfers for another network", async function () {
+    await sendCommandExpectFail(async (client : Common) => {
+      await client.signTransaction(path, transfer({ chainId: 2, assetId: nativeId, amount: 1 }));
+    });
+  });
+
+  it("shows a banner and testnet addresses on testnet", async function () {
+    await toggleNetworkSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await sendCommandAndAccept(async (client : Common) => {
+        await signTransfer(client, transfer({ chainId: 2, assetId: nativeId, amount: 1500000000 }));
+      }, [
+        { "header": "TESTNET", "prompt": "" },
+        ...transferPrompts([
+          { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+        ], "texa"),
+      ]);
+      await sendCommandExpectFail(async (client : Common) => {
+        await client.signTransaction(path, transfer({ chainId: 1, assetId: nativeId, amount: 1 }));
+      });
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await toggleNetworkSettings();
+    }
+  });
+
+  it("shows every field in expert mode", async function () {
+    const txn = transfer({ chainId: 1, assetId: nativeId, amount: 1500000000 });
+    const hash = Buffer.from(blake2b(32).update(txn).digest()).toString("base64")
+      .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+    const prompts = transferPrompts([
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+      { "header": "Asset", "prompt": nativeId, "paginate": true },
+    ]);
+    // The expert fields come after "From", before the final action.
+    prompts.splice(prompts.length - 2, 0,
+      { "header": "Fee", "prompt": "0.0025 EXA", "paginate": true },
+      { "header": "Nonce", "prompt": "42", "paginate": true },
+      { "header": "Expiry", "prompt": "Block 1000000", "paginate": true },
+      { "header": "Chain ID", "prompt": "1", "paginate": true },
+      { "header": "Transaction hash", "prompt": hash, "paginate": true },
+    );
+
+    await toggleExpertModeSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await sendCommandAndAccept(async (client : Common) => {
+        await signTransfer(client, txn);
+      }, prompts);
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await toggleExpertModeSettings();
+    }
+  });
+});
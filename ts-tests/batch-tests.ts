@@ -0,0 +1,164 @@
+import { touch, sendCommandAndAccept, answerYes, sendCommandExpectFail, BASE_URL, toggleExpertModeSettings, recipient, nativeId, transfer, withLength } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import * as blake2b from "blake2b";
+import { instantiate, Nacl } from "js-nacl";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+const recipients = [recipient, "b2".repeat(32)];
+
+// The first parameter of SIGN_BATCH.
+const batch = function(txns: Buffer[]): Buffer {
+  return Buffer.concat([Buffer.from([txns.length]), ...txns.map(withLength)]);
+}
+
+const getPublicKey = async function(): Promise<Buffer> {
+  let key: Buffer = null;
+  await sendCommandAndAccept(async (client : any) => {
+    const rv = await client.sendChunks(0x00, 0x02, 0, 0, [path]);
+    key = rv.subarray(1, 1 + rv[0]);
+  }, []);
+  await Axios.delete(BASE_URL + "/events");
+  return key;
+}
+
+// The choice to review each transfer, answered with "Yes", and with "No" after moving past "Yes".
+// On Stax and Flex both answers are on the question's screen.
+const question = { "text": "Review each?", "x": 28, "y": 11 };
+const reviewEach = touch ? [question] : [question, { "text": "Yes", "x": 55, "y": 11 }];
+const summaryOnly = touch ? [question] : [...reviewEach, { "text": "No", "x": 58, "y": 11 }];
+
+const verifyBatch = function(sigs: Buffer, txns: Buffer[], key: Buffer) {
+  expect(sigs.length).to.equal(64 * txns.length);
+  txns.forEach((txn, i) => {
+    const hash = blake2b(32).update(txn).digest();
+    expect(nacl.crypto_sign_verify_detached(sigs.subarray(64 * i, 64 * (i + 1)), hash, key)).to.equal(true);
+  });
+}
+
+describe("Batch signing tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("signs a batch of transfers after a summary", async function () {
+    const key = await getPublicKey();
+    const txns = [
+      transfer({ to: recipients[0], amount: 1500000000 }),
+      transfer({ to: recipients[1], amount: 2000000000 }),
+      transfer({ to: recipients[0], amount: 250000000 }),
+    ];
+    await sendCommandAndAccept(async (client : any) => {
+      const sigs = await client.sendChunks(0x00, 0x09, 0, 0, [batch(txns), path]);
+      verifyBatch(sigs, txns, key);
+    }, [
+      ...summaryOnly,
+      { "header": "Review Batch", "prompt": "" },
+      { "header": "Transfers", "prompt": "3", "paginate": true },
+      { "header": "Total Amount", "prompt": "3.75 EXA", "paginate": true },
+      { "header": "Recipients", "prompt": "2", "paginate": true },
+      { "header": "From", "prompt": "exa:" + key.toString("hex"), "paginate": true },
+      { "text": "Sign Batch?", "x": 32, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("shows each transfer before the summary when asked", async function () {
+    const key = await getPublicKey();
+    const txns = [
+      transfer({ to: recipients[0], amount: 1500000000 }),
+      transfer({ to: recipients[1], amount: 2000000000 }),
+    ];
+    const from = "exa:" + key.toString("hex");
+    const transferPrompts: any[] = [];
+    txns.forEach((txn, i) => {
+      transferPrompts.push(
+        { "header": "Transfer " + (i + 1) + " of 2", "prompt": "" },
+        { "header": "Amount", "prompt": ["1.5 EXA", "2 EXA"][i], "paginate": true },
+        { "header": "To", "prompt": "exa:" + recipients[i], "paginate": true },
+        { "header": "From", "prompt": from, "paginate": true },
+        { "text": "Accept Transfer?", "x": 20, "y": 11 },
+        { "text": "Confirm", "x": 43, "y": 11 },
+      );
+    });
+
+    await sendCommandAndAccept(async (client : any) => {
+      const sigs = await client.sendChunks(0x00, 0x09, 0, 0, [batch(txns), path]);
+      verifyBatch(sigs, txns, key);
+    }, [
+      ...reviewEach,
+      ...transferPrompts,
+      { "header": "Review Batch", "prompt": "" },
+      { "header": "Transfers", "prompt": "2", "paginate": true },
+      { "header": "Total Amount", "prompt": "3.5 EXA", "paginate": true },
+      { "header": "Recipients", "prompt": "2", "paginate": true },
+      { "header": "From", "prompt": from, "paginate": true },
+      { "text": "Sign Batch?", "x": 32, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ], answerYes);
+  });
+
+  it("shows the asset and total fees in expert mode", async function () {
+    const key = await getPublicKey();
+    const txns = [
+      transfer({ to: recipients[0], amount: 1500000000 }),
+      transfer({ to: recipients[1], amount: 2000000000 }),
+    ];
+
+    await toggleExpertModeSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await sendCommandAndAccept(async (client : any) => {
+        const sigs = await client.sendChunks(0x00, 0x09, 0, 0, [batch(txns), path]);
+        verifyBatch(sigs, txns, key);
+      }, [
+        ...summaryOnly,
+        { "header": "Review Batch", "prompt": "" },
+        { "header": "Transfers", "prompt": "2", "paginate": true },
+        { "header": "Total Amount", "prompt": "3.5 EXA", "paginate": true },
+        { "header": "Asset", "prompt": nativeId, "paginate": true },
+        { "header": "Recipients", "prompt": "2", "paginate": true },
+        { "header": "From", "prompt": "exa:" + key.toString("hex"), "paginate": true },
+        { "header": "Total Fees", "prompt": "0.005 EXA", "paginate": true },
+        { "text": "Sign Batch?", "x": 32, "y": 11 },
+        { "text": "Confirm", "x": 43, "y": 11 },
+      ]);
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await toggleExpertModeSettings();
+    }
+  });
+
+  it("refuses batches holding other transactions than transfers", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x09, 0, 0, [batch([transfer({ to: recipients[0], amount: 1 }), Buffer.from("0200", "hex")]), path]);
+    });
+  });
+
+  it("refuses batches of several assets", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x09, 0, 0, [batch([transfer({ to: recipients[0], amount: 1 }), transfer({ assetId: "c3".repeat(32), amount: 1 })]), path]);
+    });
+  });
+
+  it("refuses more than 32 transactions", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x09, 0, 0, [batch(Array(33).fill(transfer({ to: recipients[0], amount: 1 }))), path]);
+    });
+  });
+});
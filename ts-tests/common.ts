@@ -3,6 +3,8 @@ import Axios from 'axios';
 import Transport from "./http-transport";
 import { Common } from "hw-app-alamgu";
 import { expect } from 'chai';
+import * as fs from 'fs';
+import * as crypto from "crypto";
 
 export const VERSION = {
   major: 0,
@@ -20,14 +22,69 @@ const ignoredScreens = [ "Cancel", "Working...", "Quit", "Version"
                          , "ettings", "Blind igning"
                        ];
 
+const bothButtons = [
+  [ "button", 1, true ],
+  [ "button", 2, true ],
+  [ "button", 2, false ],
+  [ "button", 1, false ],
+];
+
 const API_PORT: number = 5005;
 
 const BASE_URL: string = `http://127.0.0.1:${API_PORT}`;
 
-const setAcceptAutomationRules = async function() {
+// The speculos model the app runs on (`-m`), set by speculos-wrapper and the Nix test runs.
+const SPECULOS_MODEL: string = process.env.SPECULOS_MODEL || "nanos";
+
+const touch: boolean = ["stax", "flex"].includes(SPECULOS_MODEL);
+
+// Where the tests touch the screen: the next page arrow of a review, the top button of a choice
+// (which is also where "Hold to sign" is held), its bottom button, and the "Reject transaction"
+// button of a review's footer.
+const touchPositions: { [model: string]: { [place: string]: [number, number] } } = {
+  stax: { next: [370, 615], top: [200, 520], bottom: [200, 615], footer: [100, 615] },
+  flex: { next: [440, 540], top: [240, 420], bottom: [240, 540], footer: [120, 540] },
+};
+
+const tap = function(place: string): any[] {
+  const [x, y] = touchPositions[SPECULOS_MODEL][place];
+  return [[ "finger", x, y, true ], [ "finger", x, y, false ]];
+}
+
+// Speculos draws a screen's texts one event at a time, so a screen's rule only acts on the text
+// that identifies it and its other texts are matched to do nothing. "Hold to sign" is pressed and
+// only released on the next screen, once the app has seen the long press; while it is held,
+// nothing else is touched.
+const touchAcceptRules = function(): any[] {
+  const [x, y] = touchPositions[SPECULOS_MODEL]["top"];
+  return [
+    { "text": "Hold to sign", "conditions": [[ "holding", false ]],
+      "actions": [[ "finger", x, y, true ], [ "setbool", "holding", true ]] },
+    { "text": "Transaction signed", "conditions": [[ "holding", true ]],
+      "actions": [[ "finger", x, y, false ], [ "setbool", "holding", false ]] },
+    { "conditions": [[ "holding", true ]], "actions": [] },
+    ... ["Swipe to review", "Reject transaction"].map(text => { return { text, "actions": tap("next") } }),
+    ... ["Continue", "Confirm"].map(text => { return { text, "actions": tap("top") } }),
+    { "text": "No", "actions": tap("bottom") },
+    { "actions": [] },
+  ];
+}
+
+// Presses right on every screen, and both buttons on "Confirm" and on the "No" of a choice.
+// On Stax and Flex, goes through reviews to "Hold to sign" and holds it, taps "Continue" and
+// "Confirm", and answers choices with "No". `rules` come first, so they can choose otherwise.
+const setAcceptAutomationRules = async function(rules: any[] = []) {
+  if (touch) {
+    await Axios.post(BASE_URL + "/automation", {
+      version: 1,
+      rules: [ ... rules, ... touchAcceptRules() ],
+    });
+    return;
+  }
   await Axios.post(BASE_URL + "/automation", {
     version: 1,
     rules: [
+      ... rules,
       ... ignoredScreens.map(txt => { return { "text": txt, "actions": [] } }),
       { "y": 16, "actions": [] },
       { "y": 31, "actions": [] },
@@ -50,6 +107,7 @@ const setAcceptAutomationRules = async function() {
           [ "button", 1, false ],
         ],
       },
+      ... ["No", " No"].map(text => { return { text, "actions": bothButtons } }),
       {
         "actions": [
           [ "button", 2, true ],
@@ -147,9 +205,63 @@ const paginate_prompts = function(page_length: number, prompts: any[]) {
   return rv;
 }
 
-const sendCommandAndAccept = async function(command : any, prompts : any[]) {
-  await setAcceptAutomationRules();
+// Set when speculos output is captured to this file and the app was built with the `speculos`
+// feature at info log level or more. Prompts are then checked against the records the app logs,
+// which are exact on every device model, instead of against OCR events.
+const PROMPT_LOG: string | undefined = process.env.PROMPT_LOG;
+
+const promptLogOffset = function(): number {
+  return PROMPT_LOG ? fs.statSync(PROMPT_LOG).size : 0;
+}
+
+const loggedPrompts = function(offset: number): any[] {
+  const lines = fs.readFileSync(PROMPT_LOG).subarray(offset).toString().split("\n");
+  let rv = [];
+  let record = null;
+  let body = "";
+  for (const line of lines) {
+    let m;
+    if ((m = line.match(/PROMPT (\{.*\})\s*$/))) {
+      record = JSON.parse(m[1]);
+      body = "";
+    } else if (record && (m = line.match(/PROMPT_BODY (".*")\s*$/))) {
+      body += JSON.parse(m[1]);
+    } else if (record && line.match(/PROMPT_END\s*$/)) {
+      if (record.kind == "final_accept_prompt") {
+        if (body) rv.push({ "text": body });
+        rv.push({ "text": "Confirm" });
+      } else if (record.kind == "choice") {
+        rv.push({ "text": body });
+      } else {
+        // Consecutive screens with the same title read as one prompt, as with OCR.
+        const last = rv[rv.length - 1];
+        if (last && last.header == record.title) {
+          last.prompt += body;
+        } else {
+          rv.push({ "header": record.title, "prompt": body });
+        }
+      }
+      record = null;
+    }
+  }
+  return rv;
+}
+
+const promptsForLog = function(prompts: any[]) {
+  return prompts.map(value => value["header"] !== undefined
+    ? { "header": value["header"], "prompt": value["prompt"] }
+    : { "text": value["text"] });
+}
+
+// Presses both buttons on the "Yes" of a choice, or taps it on Stax and Flex.
+const answerYes = touch
+  ? [{ "text": "Yes", "actions": tap("top") }, { "text": "No", "actions": [] }]
+  : ["Yes", " Yes"].map(text => { return { text, "actions": bothButtons } });
+
+const sendCommandAndAccept = async function(command : any, prompts : any[], rules : any[] = []) {
+  await setAcceptAutomationRules(rules);
   await Axios.delete(BASE_URL + "/events");
+  const logOffset = promptLogOffset();
 
   const transport = await Transport.open(BASE_URL + "/apdu");
   const client = new Common(transport, "alamgu-example");
@@ -161,6 +273,19 @@ const sendCommandAndAccept = async function(command : any, prompts : any[]) {
   }
   if(err) throw(err);
 
+  await expectPrompts(logOffset, prompts);
+}
+
+// Checks the prompts shown since the prompt log was at `logOffset` against `prompts`.
+const expectPrompts = async function(logOffset: number, prompts: any[]) {
+  if (touch && !PROMPT_LOG) {
+    expect.fail("Stax and Flex prompts are only checked against the prompt log; set PROMPT_LOG");
+  }
+  if (PROMPT_LOG) {
+    expect(loggedPrompts(logOffset)).to.deep.equal(promptsForLog(prompts));
+    return;
+  }
+
   const actual_prompts = processPrompts((await Axios.get(BASE_URL + "/events")).data["events"] as any[]);
   try {
     expect(actual_prompts).to.deep.equal(paginate_prompts(16, prompts));
@@ -174,6 +299,55 @@ const sendCommandAndAccept = async function(command : any, prompts : any[]) {
   }
 }
 
+// Presses both buttons on the first screen of a review, which jumps to "Reject", then
+// both buttons again to reject. On Stax and Flex, taps "Reject transaction" and confirms it, or
+// the "Reject" of a choice.
+const setRejectAutomationRules = async function() {
+  if (touch) {
+    await Axios.post(BASE_URL + "/automation", {
+      version: 1,
+      rules: [
+        { "text": "Reject transaction", "actions": tap("footer") },
+        { "text": "Yes, reject", "actions": tap("top") },
+        { "text": "Reject", "actions": tap("bottom") },
+        { "actions": [] },
+      ]
+    });
+    return;
+  }
+  await Axios.post(BASE_URL + "/automation", {
+    version: 1,
+    rules: [
+      ... ignoredScreens.map(txt => { return { "text": txt, "actions": [] } }),
+      { "y": 16, "actions": [] },
+      { "y": 31, "actions": [] },
+      { "y": 46, "actions": [] },
+      {
+        "actions": [
+          [ "button", 1, true ],
+          [ "button", 2, true ],
+          [ "button", 2, false ],
+          [ "button", 1, false ],
+        ],
+      }
+    ]
+  });
+}
+
+const sendCommandAndReject = async function(command : any) {
+  await setRejectAutomationRules();
+  await Axios.delete(BASE_URL + "/events");
+
+  const transport = await Transport.open(BASE_URL + "/apdu");
+  const client = new Common(transport, "alamgu-example");
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+
+  try { await command(client); } catch(e) {
+    return;
+  }
+  expect.fail("Command should have been rejected");
+}
+
 const sendCommandExpectFail = async function(command : any) {
   await setAcceptAutomationRules();
   await Axios.delete(BASE_URL + "/events");
@@ -188,15 +362,160 @@ const sendCommandExpectFail = async function(command : any) {
   expect.fail("Command should have failed");
 }
 
-let toggleBlindSigningSettings = async function() {
-  await Axios.post(BASE_URL + "/button/right", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/right", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/both", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/both", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/right", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/both", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/left", {"action":"press-and-release"});
-  await Axios.post(BASE_URL + "/button/left", {"action":"press-and-release"});
+// Like sendCommandExpectFail, but the command must fail with the status word `statusCode`, after
+// showing exactly `prompts`, such as a notice explaining the refusal.
+const sendCommandExpectStatus = async function(command : any, statusCode : number, prompts : any[] = []) {
+  await setAcceptAutomationRules();
+  await Axios.delete(BASE_URL + "/events");
+  const logOffset = promptLogOffset();
+
+  const transport = await Transport.open(BASE_URL + "/apdu");
+  const client = new Common(transport, "alamgu-example");
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+
+  let err = null;
+  try { await command(client); } catch(e) {
+    err = e;
+  }
+  if (!err) expect.fail("Command should have failed");
+  expect(err.statusCode).to.equal(statusCode);
+
+  await expectPrompts(logOffset, prompts);
+}
+
+// Speculos's default seed, for tests that compute the keys the app derives.
+const mnemonic = "glory promote mansion idle axis finger extra february uncover one trip resource lawn turtle enact monster seven myth punch hobby comfort wild raise skin";
+const seed = crypto.pbkdf2Sync(mnemonic, "mnemonic", 2048, 64, "sha512");
+
+const recipient = "a1".repeat(32);
+const nativeId = "00".repeat(32);
+
+const u64 = function(value: number | bigint): Buffer {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(BigInt(value));
+  return buf;
+}
+
+interface TransferFields {
+  chainId?: number,
+  assetId?: string,
+  to?: string,
+  amount?: number | bigint,
+}
+
+// A transfer (see docs/apdu.md), by default of 1.5 EXA to `recipient` on mainnet, always with a
+// fee of 0.0025 EXA, nonce 42 and expiry at block 1000000.
+const transfer = function({ chainId = 1, assetId = nativeId, to = recipient, amount = 1500000000 }: TransferFields = {}): Buffer {
+  const chain = Buffer.alloc(4);
+  chain.writeUInt32LE(chainId);
+  return Buffer.concat([
+    Buffer.from([1]), chain, Buffer.from(assetId, "hex"), Buffer.from(to, "hex"),
+    u64(amount), u64(2500000), u64(42), u64(1000000),
+  ]);
+}
+
+// A transaction preceded by its length, as signing instructions take it.
+const withLength = function(txn: Buffer): Buffer {
+  const length = Buffer.alloc(4);
+  length.writeUInt32LE(txn.length);
+  return Buffer.concat([length, txn]);
+}
+
+const press = async function(buttons: string[]) {
+  for (const button of buttons) {
+    await Axios.post(BASE_URL + "/button/" + button, {"action":"press-and-release"});
+  }
+}
+
+const sleep = (ms: number) => new Promise(resolve => setTimeout(resolve, ms));
+
+// Where settings are reached on Stax and Flex: the settings button of the home screen, the next
+// page arrow of the settings pages and the back arrow that leaves them.
+const settingsPositions: { [model: string]: { [place: string]: [number, number] } } = {
+  stax: { settings: [342, 55], nextPage: [370, 615], back: [36, 55] },
+  flex: { settings: [420, 55], nextPage: [440, 540], back: [40, 55] },
+};
+
+const touchAt = async function([x, y]: [number, number]) {
+  await Axios.post(BASE_URL + "/finger", {"action": "press-and-release", x, y});
+  await sleep(200);
+}
+
+// On Stax and Flex, opens the settings pages from the home screen, turns them until one shows
+// `text`, taps it, which toggles a switch or selects a choice, and goes back to the home screen.
+const touchSetting = async function(text: string) {
+  const pages = 4;
+  await touchAt(settingsPositions[SPECULOS_MODEL]["settings"]);
+  for (let page = 0; page < pages; page++) {
+    const events = (await Axios.get(BASE_URL + "/events?currentscreenonly=true")).data["events"] as any[];
+    const event = events.find(e => e["text"] == text);
+    if (event) {
+      await touchAt([event["x"] + event["w"] / 2, event["y"] + event["h"] / 2]);
+      await touchAt(settingsPositions[SPECULOS_MODEL]["back"]);
+      return;
+    }
+    await touchAt(settingsPositions[SPECULOS_MODEL]["nextPage"]);
+  }
+  expect.fail("No setting " + text + " on the settings pages");
+}
+
+// From the main screen, opens the settings submenu, toggles the entry `index` steps to the right
+// of the first one (blind signing) `times` times, leaves through "Back" and returns to the main
+// screen.
+const toggleSetting = async function(index: number, times: number = 1) {
+  const entries = 6; // Blind signing, Network, Expert mode, Hash format, Group hashes, Back
+  await press(["right", "right", "both"]);
+  await press(Array(index).fill("right"));
+  await press(Array(times).fill("both"));
+  await press(Array(entries - 1 - index).fill("right"));
+  await press(["both", "left", "left"]);
+}
+
+// Blind signing modes, in the order the Nano menu cycles through them.
+const blindSigningModes = ["Disabled", "One signature", "This session", "Always"];
+
+// Changes blind signing from mode `from` to mode `to`: on the Nano models by cycling through the
+// modes in between, and on Stax and Flex by selecting `to`.
+let setBlindSigningSettings = async function(from: string, to: string) {
+  if (touch) {
+    const choices: { [mode: string]: string } = {
+      "Disabled": "Blind signing off",
+      "One signature": "Blind sign once",
+      "This session": "Blind sign this session",
+      "Always": "Blind sign always",
+    };
+    return await touchSetting(choices[to]);
+  }
+  const steps = blindSigningModes.indexOf(to) - blindSigningModes.indexOf(from);
+  await toggleSetting(0, (steps + blindSigningModes.length) % blindSigningModes.length);
+}
+
+let toggleNetworkSettings = async function() {
+  if (touch) return await touchSetting("Testnet");
+  await toggleSetting(1);
+}
+
+let toggleExpertModeSettings = async function() {
+  if (touch) return await touchSetting("Expert mode");
+  await toggleSetting(2);
+}
+
+// Hash formats, in the order the Nano menu cycles through them.
+const hashFormats = ["Base64url", "Hex", "Base58"];
+
+// Changes the hash format from `from` to `to`: on the Nano models by cycling through the formats
+// in between, and on Stax and Flex by selecting `to`.
+let setHashFormatSettings = async function(from: string, to: string) {
+  if (touch) return await touchSetting(to + " hashes");
+  const steps = hashFormats.indexOf(to) - hashFormats.indexOf(from);
+  await toggleSetting(3, (steps + hashFormats.length) % hashFormats.length);
+}
+
+let toggleGroupHashesSettings = async function() {
+  if (touch) return await touchSetting("Group hashes");
+  await toggleSetting(4);
 }
 
-export { sendCommandAndAccept, BASE_URL, sendCommandExpectFail, toggleBlindSigningSettings }
+export { seed, recipient, nativeId, u64, transfer, withLength }
+export { touch }
+export { sendCommandAndAccept, answerYes, BASE_URL, sendCommandExpectFail, sendCommandExpectStatus, sendCommandAndReject, setBlindSigningSettings, toggleNetworkSettings, toggleExpertModeSettings, setHashFormatSettings, toggleGroupHashesSettings }
@@ -0,0 +1,122 @@
+import { sendCommandAndAccept, sendCommandExpectFail, sendCommandExpectStatus, BASE_URL, recipient, transfer, withLength } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import * as blake2b from "blake2b";
+import { instantiate, Nacl } from "js-nacl";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+// 44'/535348'/0' and 44'/535348'/1', little endian
+const paths = [Buffer.from("032c000080342b088000000080", "hex"), Buffer.from("032c000080342b088001000080", "hex")];
+
+const otherKey = Buffer.from("c4".repeat(32), "hex");
+const otherSig = Buffer.from("5a".repeat(64), "hex");
+
+// An input of a container; a path of "00" is one the device does not sign.
+const input = function(key: Buffer, path: Buffer, sig: Buffer): Buffer {
+  return Buffer.concat([key, path, Buffer.from([sig.length]), sig]);
+}
+
+const container = function(txn: Buffer, inputs: Buffer[], hints: Buffer[]): Buffer {
+  return Buffer.concat([
+    Buffer.from([1]), withLength(txn),
+    Buffer.from([inputs.length]), ...inputs,
+    Buffer.from([hints.length]), ...hints,
+  ]);
+}
+
+const getPublicKeys = async function(): Promise<Buffer[]> {
+  const keys: Buffer[] = [];
+  await sendCommandAndAccept(async (client : any) => {
+    for (const path of paths) {
+      const rv = await client.sendChunks(0x00, 0x02, 0, 0, [path]);
+      keys.push(rv.subarray(1, 1 + rv[0]));
+    }
+  }, []);
+  await Axios.delete(BASE_URL + "/events");
+  return keys;
+}
+
+describe("Container signing tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("adds its signature and keeps the other parties' inputs", async function () {
+    const [key] = await getPublicKeys();
+    const txn = transfer();
+    const unsigned = container(txn, [input(otherKey, Buffer.from("00", "hex"), otherSig), input(key, paths[0], Buffer.alloc(0))], []);
+    await sendCommandAndAccept(async (client : any) => {
+      const rv = await client.sendChunks(0x00, 0x0a, 0, 0, [unsigned]);
+      const hash = blake2b(32).update(txn).digest();
+      const sig = rv.subarray(rv.length - 1 - 64, rv.length - 1);
+      expect(nacl.crypto_sign_verify_detached(sig, hash, key)).to.equal(true);
+      const signed = container(txn, [input(otherKey, Buffer.from("00", "hex"), otherSig), input(key, paths[0], sig)], []);
+      expect(rv.toString("hex")).to.equal(signed.toString("hex"));
+    }, [
+      { "header": "Review Transfer", "prompt": "" },
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+      { "header": "To", "prompt": "exa:" + recipient, "paginate": true },
+      { "header": "From", "prompt": "exa:" + key.toString("hex"), "paginate": true },
+      { "text": "Sign Transaction?", "x": 19, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("shows a recipient named by a change hint as an own account", async function () {
+    const [key, change] = await getPublicKeys();
+    const txn = transfer({ to: change.toString("hex") });
+    await sendCommandAndAccept(async (client : any) => {
+      const rv = await client.sendChunks(0x00, 0x0a, 0, 0, [container(txn, [input(key, paths[0], Buffer.alloc(0))], [paths[1]])]);
+      const hash = blake2b(32).update(txn).digest();
+      const sig = rv.subarray(1 + 4 + txn.length + 1 + 32 + paths[0].length + 1).subarray(0, 64);
+      expect(nacl.crypto_sign_verify_detached(sig, hash, key)).to.equal(true);
+    }, [
+      { "header": "Review Transfer", "prompt": "" },
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+      { "header": "To Own Account", "prompt": "exa:" + change.toString("hex"), "paginate": true },
+      { "header": "From", "prompt": "exa:" + key.toString("hex"), "paginate": true },
+      { "text": "Sign Transaction?", "x": 19, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("refuses inputs whose key is not the device's for their path", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x0a, 0, 0, [container(transfer(), [input(otherKey, paths[0], Buffer.alloc(0))], [])]);
+    });
+  });
+
+  it("refuses change hints that do not match the recipient", async function () {
+    const [key] = await getPublicKeys();
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x0a, 0, 0, [container(transfer(), [input(key, paths[0], Buffer.alloc(0))], [paths[1]])]);
+    });
+  });
+
+  it("refuses an own path listed twice", async function () {
+    const [key] = await getPublicKeys();
+    const own = input(key, paths[0], Buffer.alloc(0));
+    await sendCommandExpectStatus(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x0a, 0, 0, [container(transfer(), [own, own], [])]);
+    }, 0x6802);
+  });
+
+  it("refuses containers it has nothing to sign in", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x0a, 0, 0, [container(transfer(), [input(otherKey, Buffer.from("00", "hex"), otherSig)], [])]);
+    });
+  });
+});
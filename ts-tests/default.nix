@@ -123,7 +123,7 @@ let
   };
 
   src = lib.sources.sourceFilesBySuffices src0 [
-    ".js" ".cjs" ".ts" ".json"
+    ".js" ".cjs" ".ts" ".json" ".csv"
   ];
 in rec {
   inherit deps yarnDepsNix yarnPackageNix thunkSource;
@@ -0,0 +1,280 @@
+import { sendCommandAndAccept, sendCommandExpectFail, sendCommandExpectStatus, BASE_URL, seed } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import { instantiate, Nacl } from "js-nacl";
+import * as crypto from "crypto";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+const legacyAddress = "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488";
+const steps = [0x8000002c, 0x80082b34, 0x80000000];
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+const hmac = (alg: string, key: Buffer | string, data: Buffer) => crypto.createHmac(alg, key).update(data).digest();
+const u32BE = (i: number) => { const b = Buffer.alloc(4); b.writeUInt32BE(i); return b; };
+const u32LE = (i: number) => { const b = Buffer.alloc(4); b.writeUInt32LE(i); return b; };
+
+// SLIP-10 for ed25519, whose steps are all hardened.
+const slip10 = function(seed: Buffer, steps: number[]) {
+  let I = hmac("sha512", "ed25519 seed", seed);
+  for (const step of steps) {
+    I = hmac("sha512", I.subarray(32), Buffer.concat([Buffer.from([0]), I.subarray(0, 32), u32BE(step)]));
+  }
+  return { key: I.subarray(0, 32), chainCode: I.subarray(32) };
+}
+
+// Just enough of ed25519 to compute the public key of an extended key, kL * B.
+const P = (BigInt(1) << BigInt(255)) - BigInt(19);
+const mod = (a: bigint) => ((a % P) + P) % P;
+const pow = (b: bigint, e: bigint) => {
+  let r = BigInt(1);
+  for (b = mod(b); e > BigInt(0); e >>= BigInt(1)) {
+    if (e & BigInt(1)) r = r * b % P;
+    b = b * b % P;
+  }
+  return r;
+};
+const inv = (a: bigint) => pow(a, P - BigInt(2));
+const D = mod(BigInt(-121665) * inv(BigInt(121666)));
+// Extended coordinates (X, Y, Z, T), with x = X/Z, y = Y/Z and xy = T/Z.
+const add = ([X1, Y1, Z1, T1]: bigint[], [X2, Y2, Z2, T2]: bigint[]) => {
+  const A = mod((Y1 - X1) * (Y2 - X2)), B = mod((Y1 + X1) * (Y2 + X2));
+  const C = mod(BigInt(2) * D * T1 * T2), E = mod(BigInt(2) * Z1 * Z2);
+  const [e, f, g, h] = [B - A, E - C, E + C, B + A];
+  return [mod(e * f), mod(g * h), mod(f * g), mod(e * h)];
+};
+// The x coordinate with the given sign for y.
+const recoverX = function(y: bigint, sign: bigint): bigint {
+  const x2 = mod((y * y - BigInt(1)) * inv(D * y * y + BigInt(1)));
+  let x = pow(x2, (P + BigInt(3)) / BigInt(8));
+  if (mod(x * x - x2) != BigInt(0)) x = mod(x * pow(BigInt(2), (P - BigInt(1)) / BigInt(4)));
+  return (x & BigInt(1)) == sign ? x : P - x;
+}
+const By = mod(BigInt(4) * inv(BigInt(5)));
+const Bx = recoverX(By, BigInt(0));
+const le = (b: Buffer) => BigInt("0x" + (Buffer.from(b).reverse().toString("hex") || "0"));
+const toLE = (n: bigint, len: number) => Buffer.from(n.toString(16).padStart(len * 2, "0"), "hex").reverse();
+const scalarMult = function(k: bigint, Q: bigint[]): bigint[] {
+  let R = [BigInt(0), BigInt(1), BigInt(1), BigInt(0)];
+  for (; k > BigInt(0); k >>= BigInt(1)) {
+    if (k & BigInt(1)) R = add(R, Q);
+    Q = add(Q, Q);
+  }
+  return R;
+}
+const encode = function([X, Y, Z]: bigint[]): Buffer {
+  const zi = inv(Z), x = mod(X * zi), y = mod(Y * zi);
+  return toLE(y | ((x & BigInt(1)) << BigInt(255)), 32);
+}
+const decode = function(b: Buffer): bigint[] {
+  const n = le(b), y = n & ((BigInt(1) << BigInt(255)) - BigInt(1));
+  const x = recoverX(y, n >> BigInt(255));
+  return [x, y, BigInt(1), mod(x * y)];
+}
+const scalarMultBase = (k: bigint) => encode(scalarMult(k, [Bx, By, BigInt(1), mod(Bx * By)]));
+
+// BIP32-Ed25519 with hardened steps, from the master key the Ledger OS makes from the seed, as
+// in Khovratovich and Law, "BIP32-Ed25519: Hierarchical Deterministic Keys over a Non-linear
+// Keyspace" (2017).
+const bip32Ed25519 = function(seed: Buffer, steps: number[]) {
+  let I = hmac("sha512", "ed25519 seed", seed);
+  while (I[31] & 0x20) I = hmac("sha512", "ed25519 seed", I);
+  const kL = Buffer.from(I.subarray(0, 32));
+  kL[0] &= 248; kL[31] &= 127; kL[31] |= 64;
+  const chainCode = hmac("sha256", "ed25519 seed", Buffer.concat([Buffer.from([1]), seed]));
+  return hardenedChild(Buffer.concat([kL, I.subarray(32), chainCode]), steps);
+}
+
+// The hardened steps of BIP32-Ed25519 from an extended private key kL | kR | chain code.
+const hardenedChild = function(xprv: Buffer, steps: number[]) {
+  let kL = xprv.subarray(0, 32), kR = xprv.subarray(32, 64), chainCode = xprv.subarray(64);
+  for (const step of steps) {
+    const Z = hmac("sha512", chainCode, Buffer.concat([Buffer.from([0]), kL, kR, u32LE(step)]));
+    chainCode = hmac("sha512", chainCode, Buffer.concat([Buffer.from([1]), kL, kR, u32LE(step)])).subarray(32);
+    kL = toLE(le(kL) + BigInt(8) * le(Z.subarray(0, 28)), 32);
+    kR = toLE((le(kR) + le(Z.subarray(32))) % (BigInt(1) << BigInt(256)), 32);
+  }
+  return { kL, kR, publicKey: scalarMultBase(le(kL)), chainCode };
+}
+
+// What a watch-only wallet does: derive a non-hardened child from an extended public key.
+const publicChild = function(publicKey: Buffer, chainCode: Buffer, index: number) {
+  const Z = hmac("sha512", chainCode, Buffer.concat([Buffer.from([2]), publicKey, u32LE(index)]));
+  const offset = scalarMultBase(BigInt(8) * le(Z.subarray(0, 28)));
+  return {
+    publicKey: encode(add(decode(publicKey), decode(offset))),
+    chainCode: hmac("sha512", chainCode, Buffer.concat([Buffer.from([3]), publicKey, u32LE(index)])).subarray(32),
+  };
+}
+
+const getExtendedPubkey = async function(client: any, scheme: number, path: Buffer) {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  const rv = await client.sendChunks(0x00, 0x08, 0, scheme, [path]);
+  return {
+    publicKey: rv.subarray(1, 1 + rv[0]),
+    chainCode: rv.subarray(2 + rv[0], 2 + rv[0] + rv[1 + rv[0]]),
+  };
+}
+
+const getPubkey = async function(client: any, scheme: number): Promise<string> {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  const rv = await client.sendChunks(0x00, 0x02, 0, scheme, [path]);
+  return rv.subarray(1, 1 + rv[0]).toString("hex");
+}
+
+describe("Derivation scheme tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("follows the SLIP-10 test vectors", async function () {
+    // SLIP-0010, test vector 1 for ed25519: the seed, then chain code and public key (without the
+    // 00 prefix SLIP-0010 gives them) for m, m/0H and m/0H/1H.
+    const vectorSeed = Buffer.from("000102030405060708090a0b0c0d0e0f", "hex");
+    const vectors: [number[], string, string][] = [
+      [[], "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb", "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"],
+      [[0x80000000], "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69", "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"],
+      [[0x80000000, 0x80000001], "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14", "1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187"],
+    ];
+    await sendCommandAndAccept(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      for (const [steps, chainCode, publicKey] of vectors) {
+        // The device, through the OS derivation the SLIP-10 scheme uses.
+        const vectorPath = Buffer.concat([Buffer.from([steps.length]), ...steps.map(u32LE)]);
+        const rv = await client.sendChunks(0x00, 0x22, 0, 0, [Buffer.concat([Buffer.from([vectorSeed.length]), vectorSeed, vectorPath])]);
+        expect(rv.subarray(0, 32).toString("hex")).to.equal(publicKey);
+        expect(rv.subarray(32).toString("hex")).to.equal(chainCode);
+
+        // The helper the other tests compute the device's keys with.
+        const expected = slip10(vectorSeed, steps);
+        expect(expected.chainCode.toString("hex")).to.equal(chainCode);
+        expect(Buffer.from(nacl.crypto_sign_seed_keypair(expected.key).signPk).toString("hex")).to.equal(publicKey);
+      }
+    }, []);
+  });
+
+  it("follows the BIP32-Ed25519 test vectors", async function () {
+    // The hardened child 0' of the extended key D1 in the test vectors of Cardano's ed25519-bip32
+    // crate (derivation scheme V2), as kL | kR | chain code. Cardano makes its master keys
+    // differently from the Ledger OS, so the vectors only cover the child steps; the device is
+    // checked against the helper, master key included, by the tests below.
+    const d1 = Buffer.from(
+      "f8a29231ee38d6c5bf715d5bac21c750577aa3798b22d79d65bf97d6fadea15a" +
+      "dcd1ee1abdf78bd4be64731a12deb94d3671784112eb6f364b871851fd1c9a24" +
+      "7384db9ad6003bbd08b3b1ddc0d07a597293ff85e961bf252b331262eddfad0d", "hex");
+    const d1H0 =
+      "60d399da83ef80d8d4f8d223239efdc2b8fef387e1b5219137ffb4e8fbdea15a" +
+      "dc9366b7d003af37c11396de9a83734e30e05e851efa32745c9cd7b42712c890" +
+      "608763770eddf77248ab652984b21b849760d1da74a6f5bd633ce41adceef07a";
+    const { kL, kR, chainCode } = hardenedChild(d1, [0x80000000]);
+    expect(Buffer.concat([kL, kR, chainCode]).toString("hex")).to.equal(d1H0);
+  });
+
+  it("keeps the legacy scheme's keys", async function () {
+    // The legacy scheme uses the BIP32-Ed25519 kL as an RFC 8032 private key.
+    const { kL } = bip32Ed25519(seed, steps);
+    expect(Buffer.from(nacl.crypto_sign_seed_keypair(kL).signPk).toString("hex")).to.equal(legacyAddress);
+    await sendCommandAndAccept(async (client : Common) => {
+      expect(await getPubkey(client, 0)).to.equal(legacyAddress);
+    }, []);
+  });
+
+  it("derives SLIP-10 keys", async function () {
+    const expected = Buffer.from(nacl.crypto_sign_seed_keypair(slip10(seed, steps).key).signPk).toString("hex");
+    await sendCommandAndAccept(async (client : Common) => {
+      expect(await getPubkey(client, 1)).to.equal(expected);
+    }, []);
+  });
+
+  it("derives BIP32-Ed25519 keys", async function () {
+    const expected = bip32Ed25519(seed, steps).publicKey.toString("hex");
+    await sendCommandAndAccept(async (client : Common) => {
+      expect(await getPubkey(client, 2)).to.equal(expected);
+    }, []);
+  });
+
+  for (const [scheme, name] of [[1, "SLIP-10"], [2, "BIP32-Ed25519"]] as [number, string][]) {
+    it("signs with " + name + " keys", async function () {
+      const publicKey = scheme == 1
+        ? Buffer.from(nacl.crypto_sign_seed_keypair(slip10(seed, steps).key).signPk)
+        : bip32Ed25519(seed, steps).publicKey;
+      const service = "example.com";
+      const nonce = Buffer.alloc(32, 0x6e);
+      const challenge = Buffer.concat([nonce, Buffer.from([service.length]), Buffer.from(service)]);
+      await sendCommandAndAccept(async (client : any) => {
+        client.sendChunks = client.sendWithBlocks; // Use Block protocol
+        const sig = await client.sendChunks(0x00, 0x05, 0, scheme, [challenge, path]);
+        const message = Buffer.concat([Buffer.from("alamgu-example ownership proof"), publicKey, challenge.subarray(32, 33), Buffer.from(service), nonce]);
+        expect(nacl.crypto_sign_verify_detached(sig, message, publicKey)).to.equal(true);
+      }, [
+        { "header": "Prove Ownership", "prompt": "" },
+        { "header": "Ownership", "prompt": "Prove ownership of exa:" + publicKey.toString("hex") + " to " + service, "paginate": true },
+        { "text": "Sign Proof?", "x": 33, "y": 11 },
+        { "text": "Confirm", "x": 43, "y": 11 },
+      ]);
+    });
+  }
+
+  it("exports BIP32-Ed25519 extended public keys for accounts", async function () {
+    const expected = bip32Ed25519(seed, steps);
+    // 44'/535348'/0'/0/5, little endian
+    const child = Buffer.from("052c000080342b08800000008000000000" + "05000000", "hex");
+    await sendCommandAndAccept(async (client : Common) => {
+      const xpub = await getExtendedPubkey(client, 2, path);
+      expect(xpub.publicKey.toString("hex")).to.equal(expected.publicKey.toString("hex"));
+      expect(xpub.chainCode.toString("hex")).to.equal(expected.chainCode.toString("hex"));
+
+      // The watch-only wallet's addresses are the device's.
+      const external = publicChild(xpub.publicKey, xpub.chainCode, 0);
+      const address = publicChild(external.publicKey, external.chainCode, 5).publicKey;
+      const rv = await (client as any).sendChunks(0x00, 0x02, 0, 2, [child]);
+      expect(rv.subarray(1, 1 + rv[0]).toString("hex")).to.equal(address.toString("hex"));
+    }, [
+      { "header": "Extended Key", "prompt": "Export extended public key for account 0?", "paginate": true },
+      { "text": "Export Key?", "x": 31, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("refuses extended public keys of other schemes than BIP32-Ed25519", async function () {
+    for (const scheme of [0, 1, 3]) {
+      await sendCommandExpectFail(async (client : Common) => {
+        await getExtendedPubkey(client, scheme, path);
+      });
+    }
+  });
+
+  it("refuses extended public keys below the account level", async function () {
+    // 44'/535348'/0'/0'/0', little endian
+    const deeper = Buffer.from("052c000080342b0880000000800000008000000080", "hex");
+    await sendCommandExpectFail(async (client : Common) => {
+      await getExtendedPubkey(client, 2, deeper);
+    });
+  });
+
+  it("refuses SLIP-10 paths with non-hardened steps", async function () {
+    // 44'/535348'/0'/0, little endian
+    const soft = Buffer.from("042c000080342b08800000008000000000", "hex");
+    await sendCommandExpectStatus(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x02, 0, 1, [soft]);
+    }, 0x6802);
+  });
+
+  it("rejects an unknown scheme", async function () {
+    await sendCommandExpectFail(async (client : Common) => {
+      await getPubkey(client, 4);
+    });
+  });
+});
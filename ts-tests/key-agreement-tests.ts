@@ -0,0 +1,58 @@
+import { sendCommandAndAccept, sendCommandExpectStatus, BASE_URL } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import { instantiate, Nacl } from "js-nacl";
+import * as crypto from "crypto";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+const label = Buffer.from("alamgu-example x25519 key agreement");
+const address = "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488";
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+const keyAgreement = async function(client: any, peer: Uint8Array): Promise<Buffer> {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  return await client.sendChunks(0x00, 0x06, 0, 0, [Buffer.from(peer), path]);
+}
+
+describe("Key agreement tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("derives the same key as the peer", async function () {
+    const peer = nacl.crypto_box_keypair();
+    await sendCommandAndAccept(async (client : Common) => {
+      const key = await keyAgreement(client, peer.boxPk);
+
+      const ours = nacl.crypto_box_pk_from_sign_pk(Buffer.from(address, "hex"));
+      const shared = nacl.crypto_scalarmult(peer.boxSk, ours);
+      const [first, second] = [Buffer.from(ours), Buffer.from(peer.boxPk)].sort(Buffer.compare);
+      const expected = crypto.createHash("sha256").update(Buffer.concat([label, shared, first, second])).digest();
+      expect(key.toString("hex")).to.equal(expected.toString("hex"));
+    }, [
+      { "header": "Key Agreement", "prompt": "" },
+      { "header": "Account", "prompt": "exa:" + address, "paginate": true },
+      { "header": "Peer", "prompt": Buffer.from(peer.boxPk).toString("hex"), "paginate": true },
+      { "text": "Share Key?", "x": 35, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("refuses a peer key of small order without asking to share a key", async function () {
+    await sendCommandExpectStatus(async (client : Common) => {
+      await keyAgreement(client, new Uint8Array(32));
+    }, 0x6802);
+  });
+});
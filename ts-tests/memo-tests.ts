@@ -0,0 +1,121 @@
+import { sendCommandAndAccept, sendCommandExpectFail, BASE_URL } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import { instantiate, Nacl } from "js-nacl";
+import * as crypto from "crypto";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+const label = Buffer.from("alamgu-example memo");
+const address = "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488";
+const nonce = Buffer.from("4e".repeat(24), "hex");
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+// HChaCha20 (draft-irtf-cfrg-xchacha), which node's crypto does not provide.
+const hchacha20 = function(key: Buffer, nonce: Buffer): Buffer {
+  const s = new Uint32Array(16);
+  s.set([0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+  for (let i = 0; i < 8; i++) s[4 + i] = key.readUInt32LE(4 * i);
+  for (let i = 0; i < 4; i++) s[12 + i] = nonce.readUInt32LE(4 * i);
+  const rotl = (v: number, c: number) => (v << c) | (v >>> (32 - c));
+  const qr = (a: number, b: number, c: number, d: number) => {
+    s[a] += s[b]; s[d] = rotl(s[d] ^ s[a], 16);
+    s[c] += s[d]; s[b] = rotl(s[b] ^ s[c], 12);
+    s[a] += s[b]; s[d] = rotl(s[d] ^ s[a], 8);
+    s[c] += s[d]; s[b] = rotl(s[b] ^ s[c], 7);
+  };
+  for (let i = 0; i < 10; i++) {
+    qr(0, 4, 8, 12); qr(1, 5, 9, 13); qr(2, 6, 10, 14); qr(3, 7, 11, 15);
+    qr(0, 5, 10, 15); qr(1, 6, 11, 12); qr(2, 7, 8, 13); qr(3, 4, 9, 14);
+  }
+  const out = Buffer.alloc(32);
+  [0, 1, 2, 3, 12, 13, 14, 15].forEach((w, i) => out.writeUInt32LE(s[w], 4 * i));
+  return out;
+}
+
+// XChaCha20-Poly1305 with no associated data: ciphertext followed by the tag.
+const encrypt = function(key: Buffer, plaintext: Buffer): Buffer {
+  const cipher = crypto.createCipheriv("chacha20-poly1305", hchacha20(key, nonce.subarray(0, 16)),
+    Buffer.concat([Buffer.alloc(4), nonce.subarray(16)]), { authTagLength: 16 });
+  return Buffer.concat([cipher.update(plaintext), cipher.final(), cipher.getAuthTag()]);
+}
+
+// What a sender does: agree on a key with the account's X25519 key, and encrypt to it.
+const encryptedMemo = function(sender: any, memo: Buffer): Buffer {
+  const ours = nacl.crypto_box_pk_from_sign_pk(Buffer.from(address, "hex"));
+  const shared = nacl.crypto_scalarmult(sender.boxSk, ours);
+  const [first, second] = [Buffer.from(ours), Buffer.from(sender.boxPk)].sort(Buffer.compare);
+  const key = crypto.createHash("sha256").update(Buffer.concat([label, shared, first, second])).digest();
+  const ciphertext = encrypt(key, memo);
+  const length = Buffer.alloc(2);
+  length.writeUInt16LE(ciphertext.length);
+  return Buffer.concat([Buffer.from(sender.boxPk), nonce, length, ciphertext]);
+}
+
+const decryptMemo = async function(client: any, payload: Buffer): Promise<Buffer> {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  return await client.sendChunks(0x00, 0x07, 0, 0, [payload, path]);
+}
+
+const memoPrompts = function(sender: any, title: string, memo: string) {
+  return [
+    { "header": "Read Memo", "prompt": "" },
+    { "header": "To", "prompt": "exa:" + address, "paginate": true },
+    { "header": "From", "prompt": Buffer.from(sender.boxPk).toString("hex"), "paginate": true },
+    { "header": title, "prompt": memo, "paginate": true },
+    { "text": "Close Memo?", "x": 31, "y": 11 },
+    { "text": "Confirm", "x": 43, "y": 11 },
+  ];
+}
+
+describe("Memo tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("shows a memo encrypted to the account", async function () {
+    const sender = nacl.crypto_box_keypair();
+    const memo = "Invoice 1042: thanks for the coffee beans, see you next week!";
+    await sendCommandAndAccept(async (client : Common) => {
+      const result = await decryptMemo(client, encryptedMemo(sender, Buffer.from(memo)));
+      // The memo is only shown on the device.
+      expect(result.length).to.equal(0);
+    }, memoPrompts(sender, "Memo", memo));
+  });
+
+  it("shows a binary memo as hex", async function () {
+    const sender = nacl.crypto_box_keypair();
+    const memo = Buffer.from("00ff10", "hex");
+    await sendCommandAndAccept(async (client : Common) => {
+      await decryptMemo(client, encryptedMemo(sender, memo));
+    }, memoPrompts(sender, "Memo (hex)", "00ff10"));
+  });
+
+  it("shows an error for a modified memo", async function () {
+    const sender = nacl.crypto_box_keypair();
+    const payload = encryptedMemo(sender, Buffer.from("Pay 10 EXA"));
+    payload[32 + 24 + 2] ^= 1;
+    await sendCommandExpectFail(async (client : Common) => {
+      await decryptMemo(client, payload);
+    });
+  });
+
+  it("refuses a memo shorter than its tag", async function () {
+    const sender = nacl.crypto_box_keypair();
+    const payload = Buffer.concat([Buffer.from(sender.boxPk), nonce, Buffer.from([4, 0]), Buffer.alloc(4)]);
+    await sendCommandExpectFail(async (client : Common) => {
+      await decryptMemo(client, payload);
+    });
+  });
+});
@@ -0,0 +1,57 @@
+import { sendCommandAndAccept, sendCommandExpectFail, BASE_URL } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import { instantiate, Nacl } from "js-nacl";
+
+let nacl : Nacl =null;
+
+instantiate(n => { nacl=n; });
+
+const domain = Buffer.from("alamgu-example ownership proof");
+const address = "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488";
+const nonce = Buffer.from("6e".repeat(32), "hex");
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+const challenge = function(service: string): Buffer {
+  return Buffer.concat([nonce, Buffer.from([service.length]), Buffer.from(service, "ascii")]);
+}
+
+const proveOwnership = async function(client: any, service: string): Promise<Buffer> {
+  client.sendChunks = client.sendWithBlocks; // Use Block protocol
+  return await client.sendChunks(0x00, 0x05, 0, 0, [challenge(service), path]);
+}
+
+describe("Ownership proof tests", function() {
+  before( async function() {
+    while(!nacl) await new Promise(r => setTimeout(r, 100));
+  })
+
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  it("signs a domain separated proof for the service", async function () {
+    const service = "example.com";
+    await sendCommandAndAccept(async (client : Common) => {
+      const sig = await proveOwnership(client, service);
+      const message = Buffer.concat([domain, Buffer.from(address, "hex"), challenge(service).subarray(32, 33), Buffer.from(service), nonce]);
+      expect(nacl.crypto_sign_verify_detached(sig, message, Buffer.from(address, "hex"))).to.equal(true);
+    }, [
+      { "header": "Prove Ownership", "prompt": "" },
+      { "header": "Ownership", "prompt": "Prove ownership of exa:" + address + " to " + service, "paginate": true },
+      { "text": "Sign Proof?", "x": 33, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("rejects an unprintable service name", async function () {
+    await sendCommandExpectFail(async (client : Common) => {
+      await proveOwnership(client, "example\ncom");
+    });
+  });
+});
@@ -1,4 +1,4 @@
-import { sendCommandAndAccept } from "./common";
+import { sendCommandAndAccept, sendCommandExpectFail } from "./common";
 
 interface TestParsersSchema {
   bytes_params: BytesParams,
@@ -6,6 +6,11 @@ interface TestParsersSchema {
   u32_params: U32Params,
   u64_params: U64Params,
   darray_params: DArrayParams,
+  enum_params: EnumParams,
+  option_params: OptionParams,
+  byte_string_params: string,
+  leb128_params: LEB128Params,
+  nested_params: NestedParams,
 }
 
 interface BytesParams {
@@ -33,6 +38,77 @@ interface DArrayParams {
   v2: number[],
 }
 
+type EnumValue =
+  { tag: "Empty" }
+  | { tag: "Number", value: number }
+  | { tag: "Bytes", value: string };
+
+interface EnumParams {
+  v1: EnumValue,
+  v2: EnumValue,
+}
+
+interface OptionParams {
+  v1: number | null,
+  v2: string | null,
+}
+
+interface LEB128Params {
+  v1: bigint,
+  v2: bigint,
+}
+
+interface NestedEntry {
+  amount: bigint,
+  flag: number | null,
+}
+
+interface NestedParams {
+  id: number,
+  kind: number,
+  entries: NestedEntry[],
+}
+
+function encodeLEB128(v: bigint): Buffer {
+  const bytes = [];
+  do {
+    let byte = Number(v & BigInt(0x7f));
+    v >>= BigInt(7);
+    if (v != BigInt(0)) byte |= 0x80;
+    bytes.push(byte);
+  } while (v != BigInt(0));
+  return Buffer.from(bytes);
+}
+
+function encodeU8(v: number): Buffer {
+  const buf = Buffer.alloc(1);
+  buf.writeUInt8(v);
+  return buf;
+}
+
+function encodeU32LE(v: number): Buffer {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(v);
+  return buf;
+}
+
+function encodeByteDArray(hex: string): Buffer {
+  const bytes = Buffer.from(hex, "hex");
+  return Buffer.concat([encodeU8(bytes.length), bytes]);
+}
+
+function encodeEnum(v: EnumValue): Buffer {
+  switch (v.tag) {
+    case "Empty": return encodeU8(0);
+    case "Number": return Buffer.concat([encodeU8(1), encodeU32LE(v.value)]);
+    case "Bytes": return Buffer.concat([encodeU8(2), encodeByteDArray(v.value)]);
+  }
+}
+
+function encodeOptional(v: Buffer | null): Buffer {
+  return v === null ? encodeU8(0) : Buffer.concat([encodeU8(1), v]);
+}
+
 function buildPayload(obj: TestParsersSchema): Buffer {
   const bytes_v1 = Buffer.alloc(1);
   bytes_v1.writeUInt8(obj.bytes_params.v1);
@@ -62,31 +138,187 @@ function buildPayload(obj: TestParsersSchema): Buffer {
   const len_v2 = Buffer.alloc(1);
   len_v2.writeUInt8(obj.darray_params.v2.length);
 
+  const byte_string = Buffer.from(obj.byte_string_params, "hex");
+  const byte_string_len = Buffer.alloc(2);
+  byte_string_len.writeUInt16LE(byte_string.length);
+
+  const nested_entries = obj.nested_params.entries.map(e => Buffer.concat([
+    encodeLEB128(e.amount),
+    encodeOptional(e.flag === null ? null : encodeU8(e.flag)),
+  ]));
+
   return Buffer.concat(
     [ bytes_v1, bytes_v2,
       u16_v1, u16_v2,
       u64_v1, u64_v2,
       len_v1, darrayparams_v1,
-      len_v2, darrayparams_v2
+      len_v2, darrayparams_v2,
+      encodeEnum(obj.enum_params.v1), encodeEnum(obj.enum_params.v2),
+      encodeOptional(obj.option_params.v1 === null ? null : encodeU32LE(obj.option_params.v1)),
+      encodeOptional(obj.option_params.v2 === null ? null : encodeByteDArray(obj.option_params.v2)),
+      byte_string_len, byte_string,
+      encodeLEB128(obj.leb128_params.v1), encodeLEB128(obj.leb128_params.v2),
+      encodeU32LE(obj.nested_params.id), encodeU8(obj.nested_params.kind),
+      encodeU8(nested_entries.length), ...nested_entries,
     ]);
 }
 
+// Renders bytes the way Rust's {:02x?} does for a byte slice.
+function debugHex(hex: string): string {
+  return "[" + (hex.match(/../g) || []).map(b => b.toLowerCase()).join(", ") + "]";
+}
+
+// Renders bytes the way Rust's {:?} does for a byte slice.
+function debugBytes(hex: string): string {
+  return "[" + Array.from(Buffer.from(hex, "hex")).join(", ") + "]";
+}
+
+function debugEnum(v: EnumValue): string {
+  switch (v.tag) {
+    case "Empty": return "Empty";
+    case "Number": return `Number(${v.value})`;
+    case "Bytes": return `Bytes(${debugBytes(v.value)})`;
+  }
+}
+
+function debugOption<T>(v: T | null, f: (x: T) => string): string {
+  return v === null ? "None" : `Some(${f(v)})`;
+}
+
+// Consecutive screens with the same header are merged into one prompt.
+function repeatedPrompt(header: string, prompts: string[]): any[] {
+  return prompts.length == 0 ? [] : [{ header, "prompt": prompts.join("") }];
+}
+
+function expectedPrompts(obj: TestParsersSchema): any[] {
+  return [
+    {
+      "header": "Got Bytes",
+      "prompt": `v1: ${obj.bytes_params.v1}, v2: ${debugHex(obj.bytes_params.v2)}`,
+      "paginate": true,
+    },
+    {
+      "header": "Got U16",
+      "prompt": `v1: ${obj.u16_params.v1}, v2: ${obj.u16_params.v2}`,
+    },
+    {
+      "header": "Got U64",
+      "prompt": `v1: ${obj.u64_params.v1}, v2: ${obj.u64_params.v2}`,
+      "paginate": true,
+    },
+    ...repeatedPrompt("Got U32", obj.darray_params.v2.map(v => `v1: ${v}, v2: ${v}`)),
+    {
+      "header": "Got Darray",
+      "prompt": `v1: ${debugHex(obj.darray_params.v1)}`,
+    },
+    ...extendedPrompts(obj),
+    {
+      "header": "Parse done",
+      "prompt": "",
+    },
+  ];
+}
+
+function extendedPrompts(obj: TestParsersSchema): any[] {
+  const byte_string_len = obj.byte_string_params.length / 2;
+  return [
+    {
+      "header": "Got Enum",
+      "prompt": `v1: ${debugEnum(obj.enum_params.v1)}, v2: ${debugEnum(obj.enum_params.v2)}`,
+    },
+    {
+      "header": "Got Option",
+      "prompt": `v1: ${debugOption(obj.option_params.v1, v => v.toString())}, v2: ${debugOption(obj.option_params.v2, debugBytes)}`,
+    },
+    {
+      "header": "Got Byte String",
+      "prompt": `len: ${byte_string_len}, v: ${debugHex(obj.byte_string_params)}`,
+    },
+    {
+      "header": "Got LEB128",
+      "prompt": `v1: ${obj.leb128_params.v1}, v2: ${obj.leb128_params.v2}`,
+    },
+    ...repeatedPrompt("Got Entry", obj.nested_params.entries.map(e =>
+      `amount: ${e.amount}, flag: ${debugOption(e.flag, v => v.toString())}`)),
+    {
+      "header": "Got Nested",
+      "prompt": `id: ${obj.nested_params.id}, kind: ${obj.nested_params.kind}, entries: ${obj.nested_params.entries.length}`,
+    },
+  ];
+}
+
 const doTestParsersAPDU = async function(
   client: any,
   obj: TestParsersSchema,
+): Promise<void> {
+  await doTestParsersRawAPDU(client, buildPayload(obj));
+}
+
+const doTestParsersRawAPDU = async function(
+  client: any,
+  payload: Buffer,
 ): Promise<void> {
   const cla = 0x00;
   const ins = 0x20;
   const p1 = 0;
   const p2 = 0;
 
-  const payload = buildPayload(obj);
-
   // there is no return value
   await client.sendChunks(cla, ins, p1, p2, [payload]);
   return;
 }
 
+const baseObj: TestParsersSchema = {
+  bytes_params: {
+    v1: 255,
+    v2: "1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF",
+  },
+  u16_params:  {
+    v1: 345,
+    v2: 567,
+  },
+  u32_params:  {
+    v1: 78901234,
+    v2: 90123456,
+  },
+  u64_params:  {
+    v1: BigInt("9007199254740992"),
+    v2: BigInt("18014398509481982"),
+  },
+  darray_params:  {
+    v1: "12345678",
+    v2: [9, 8, 7, 6],
+  },
+  enum_params: {
+    v1: { tag: "Number", value: 4000000000 },
+    v2: { tag: "Bytes", value: "0102" },
+  },
+  option_params: {
+    v1: 42,
+    v2: null,
+  },
+  byte_string_params: "deadbeef",
+  leb128_params: {
+    v1: BigInt(624485),
+    v2: BigInt(0),
+  },
+  nested_params: {
+    id: 7,
+    kind: 3,
+    entries: [
+      { amount: BigInt(300), flag: 1 },
+      { amount: BigInt(5), flag: null },
+    ],
+  },
+};
+
+const sendCommandExpectParseFail = async function(payload: Buffer) {
+  await sendCommandExpectFail(async (client : any) => {
+    client.sendChunks = client.sendWithBlocks; // Use Block protocol
+    await doTestParsersRawAPDU(client, payload);
+  });
+}
+
 describe('parsers tests', () => {
 
   // afterEach( async function() {
@@ -97,30 +329,7 @@ describe('parsers tests', () => {
   it('can parse a bunch of data', async () => {
 
     await sendCommandAndAccept(async (client : any) => {
-      const obj = {
-
-        bytes_params: {
-          v1: 255,
-          v2: "1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF",
-        },
-        u16_params:  {
-          v1: 345,
-          v2: 567,
-        },
-        u32_params:  {
-          v1: 78901234,
-          v2: 90123456,
-        },
-        u64_params:  {
-          v1: BigInt("9007199254740992"),
-          v2: BigInt("18014398509481982"),
-        },
-        darray_params:  {
-          v1: "12345678",
-          v2: [9, 8, 7, 6],
-        },
-      };
-      await doTestParsersAPDU(client, obj);
+      await doTestParsersAPDU(client, baseObj);
       return;
     }, [
       {
@@ -145,10 +354,113 @@ describe('parsers tests', () => {
         "header": "Got Darray",
         "prompt": "v1: [12, 34, 56, 78]",
       },
+      {
+        "header": "Got Enum",
+        "prompt": "v1: Number(4000000000), v2: Bytes([1, 2])",
+      },
+      {
+        "header": "Got Option",
+        "prompt": "v1: Some(42), v2: None",
+      },
+      {
+        "header": "Got Byte String",
+        "prompt": "len: 4, v: [de, ad, be, ef]",
+      },
+      {
+        "header": "Got LEB128",
+        "prompt": "v1: 624485, v2: 0",
+      },
+      {
+        "header": "Got Entry",
+        "prompt": "amount: 300, flag: Some(1)amount: 5, flag: None",
+      },
+      {
+        "header": "Got Nested",
+        "prompt": "id: 7, kind: 3, entries: 2",
+      },
       {
         "header": "Parse done",
         "prompt": "",
       },
     ]);
   });
+
+  it('can parse empty arrays and absent values', async () => {
+    const obj: TestParsersSchema = {
+      ...baseObj,
+      darray_params: { v1: "", v2: [] },
+      enum_params: { v1: { tag: "Empty" }, v2: { tag: "Bytes", value: "" } },
+      option_params: { v1: null, v2: "" },
+      byte_string_params: "",
+      nested_params: { id: 0, kind: 0, entries: [] },
+    };
+    await sendCommandAndAccept(async (client : any) => {
+      await doTestParsersAPDU(client, obj);
+    }, expectedPrompts(obj));
+  });
+
+  it('can parse maximum-length arrays and values', async () => {
+    const obj: TestParsersSchema = {
+      ...baseObj,
+      u64_params: {
+        v1: BigInt("18446744073709551615"),
+        v2: BigInt("18446744073709551615"),
+      },
+      darray_params: { v1: "ab".repeat(24), v2: [0xffffffff, 0, 1, 2] },
+      enum_params: { v1: { tag: "Number", value: 0xffffffff }, v2: { tag: "Bytes", value: "ff".repeat(8) } },
+      option_params: { v1: 0xffffffff, v2: "01".repeat(8) },
+      byte_string_params: "5a".repeat(64),
+      leb128_params: { v1: BigInt("18446744073709551615"), v2: BigInt(127) },
+      nested_params: {
+        id: 0xffffffff,
+        kind: 255,
+        entries: [
+          { amount: BigInt(128), flag: 0 },
+          { amount: BigInt(16384), flag: 255 },
+          { amount: BigInt("18446744073709551615"), flag: null },
+          { amount: BigInt(1), flag: 1 },
+        ],
+      },
+    };
+    await sendCommandAndAccept(async (client : any) => {
+      await doTestParsersAPDU(client, obj);
+    }, expectedPrompts(obj));
+  });
+
+  it('rejects an array longer than its maximum', async () => {
+    const payload = buildPayload({ ...baseObj, darray_params: { v1: "ab".repeat(25), v2: [] } });
+    await sendCommandExpectParseFail(payload);
+  });
+
+  it('rejects a byte string longer than its maximum', async () => {
+    const payload = buildPayload({ ...baseObj, byte_string_params: "5a".repeat(65) });
+    await sendCommandExpectParseFail(payload);
+  });
+
+  it('rejects an unknown enum tag', async () => {
+    const payload = buildPayload(baseObj);
+    // The first enum tag directly follows the fixed-size fields and both darrays
+    const tagOffset = 1 + 32 + 2 + 2 + 8 + 8 + 1 + 4 + 1 + 4 * 8;
+    payload.writeUInt8(3, tagOffset);
+    await sendCommandExpectParseFail(payload);
+  });
+
+  it('rejects a non-minimal LEB128 encoding', async () => {
+    const good = buildPayload(baseObj);
+    const leb = encodeLEB128(baseObj.leb128_params.v1);
+    const at = good.indexOf(leb);
+    const padded = Buffer.from(leb);
+    padded[padded.length - 1] |= 0x80;
+    const payload = Buffer.concat([good.subarray(0, at), padded, Buffer.from([0]), good.subarray(at + leb.length)]);
+    await sendCommandExpectParseFail(payload);
+  });
+
+  it('rejects an overflowing LEB128 encoding', async () => {
+    const good = buildPayload(baseObj);
+    const leb = encodeLEB128(baseObj.leb128_params.v1);
+    const at = good.indexOf(leb);
+    const overflow = Buffer.from([0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]);
+    const payload = Buffer.concat([good.subarray(0, at), overflow, good.subarray(at + leb.length)]);
+    await sendCommandExpectParseFail(payload);
+  });
 });
@@ -1,4 +1,4 @@
-import { sendCommandAndAccept, BASE_URL, } from "./common";
+import { sendCommandAndAccept, sendCommandExpectFail, sendCommandExpectStatus, BASE_URL, } from "./common";
 import { expect } from 'chai';
 import { describe, it } from 'mocha';
 import Axios from 'axios';
@@ -34,7 +34,7 @@ describe('public key tests', () => {
       },
       {
         "header": "Address",
-        "prompt": "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
         "paginate": true,
       },
       {
@@ -44,4 +44,41 @@ describe('public key tests', () => {
       },
     ]);
   });
+
+  it('accepts paths with big endian steps or as an account index', async () => {
+    const bigEndian = Buffer.alloc(1 + 5 * 4);
+    bigEndian.writeUInt8(5, 0);
+    [0x8000002c, 0x80082b34, 0x80000007, 0x80000000, 0x80000000].forEach((step, i) => bigEndian.writeUInt32BE(step, 1 + 4 * i));
+    const account = Buffer.alloc(4);
+    account.writeUInt32BE(7);
+
+    await sendCommandAndAccept(async (client : Common) => {
+      const expected = new Buffer((await client.getPublicKey("44'/535348'/7'/0'/0'")).publicKey).toString('hex');
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      for (const [p1, payload] of [[1, bigEndian], [2, account]] as [number, Buffer][]) {
+        const rv = await client.sendChunks(0x00, 0x02, p1, 0, [payload]);
+        expect(rv.subarray(1, 1 + rv[0]).toString('hex')).to.equal(expected);
+      }
+    }, []);
+  });
+
+  it('rejects a hardened account index', async () => {
+    const account = Buffer.alloc(4);
+    account.writeUInt32BE(0x80000000);
+
+    await sendCommandExpectFail(async (client : Common) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x02, 2, 0, [account]);
+    });
+  });
+
+  it('rejects a path followed by trailing bytes', async () => {
+    const path = Buffer.alloc(1 + 3 * 4);
+    path.writeUInt8(3, 0);
+    [0x8000002c, 0x80082b34, 0x80000000].forEach((step, i) => path.writeUInt32LE(step, 1 + 4 * i));
+
+    await sendCommandExpectStatus(async (client : Common) => {
+      await client.sendChunks(0x00, 0x02, 0, 0, [Buffer.concat([path, Buffer.from([0])])]);
+    }, 0x6A80);
+  });
 });
@@ -0,0 +1,180 @@
+import { sendCommandAndAccept, sendCommandExpectFail, BASE_URL, seed, recipient, transfer, withLength } from "./common";
+import { expect } from 'chai';
+import { describe, it } from 'mocha';
+import Axios from 'axios';
+import { Common } from "hw-app-alamgu";
+import * as blake2b from "blake2b";
+import * as crypto from "crypto";
+import * as fs from "fs";
+import * as path from "path";
+
+const steps = [0x8000002c, 0x80082b34, 0x80000000];
+
+// 44'/535348'/0', little endian
+const path = Buffer.from("032c000080342b088000000080", "hex");
+
+// secp256k1, with points as affine [x, y] and null for the point at infinity.
+const P = (BigInt(1) << BigInt(256)) - (BigInt(1) << BigInt(32)) - BigInt(977);
+const N = BigInt("0xfffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141");
+const G = [
+  BigInt("0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
+  BigInt("0x483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"),
+];
+const mod = (a: bigint, m: bigint = P) => ((a % m) + m) % m;
+const pow = (b: bigint, e: bigint) => {
+  let r = BigInt(1);
+  for (b = mod(b); e > BigInt(0); e >>= BigInt(1)) {
+    if (e & BigInt(1)) r = r * b % P;
+    b = b * b % P;
+  }
+  return r;
+};
+const inv = (a: bigint) => pow(a, P - BigInt(2));
+const add = function(a: bigint[] | null, b: bigint[] | null): bigint[] | null {
+  if (a == null) return b;
+  if (b == null) return a;
+  if (a[0] == b[0] && a[1] != b[1]) return null;
+  const lambda = a[1] == b[1]
+    ? mod(BigInt(3) * a[0] * a[0] * inv(BigInt(2) * a[1]))
+    : mod((b[1] - a[1]) * inv(b[0] - a[0]));
+  const x = mod(lambda * lambda - a[0] - b[0]);
+  return [x, mod(lambda * (a[0] - x) - a[1])];
+}
+const mul = function(point: bigint[] | null, k: bigint): bigint[] | null {
+  let r: bigint[] | null = null;
+  for (; k > BigInt(0); k >>= BigInt(1)) {
+    if (k & BigInt(1)) r = add(r, point);
+    point = add(point, point);
+  }
+  return r;
+}
+const num = (b: Buffer) => BigInt("0x" + b.toString("hex"));
+
+const taggedHash = function(tag: string, ...parts: Buffer[]): Buffer {
+  const t = crypto.createHash("sha256").update(tag).digest();
+  return crypto.createHash("sha256").update(Buffer.concat([t, t, ...parts])).digest();
+}
+
+// BIP340 verification.
+const verify = function(publicKey: Buffer, message: Buffer, sig: Buffer): boolean {
+  const x = num(publicKey);
+  if (x >= P) return false;
+  const y = pow(x * x * x + BigInt(7), (P + BigInt(1)) / BigInt(4));
+  if (mod(y * y) != mod(x * x * x + BigInt(7))) return false;
+  const point = [x, y & BigInt(1) ? P - y : y];
+  const r = num(sig.subarray(0, 32)), s = num(sig.subarray(32));
+  if (r >= P || s >= N) return false;
+  const e = mod(num(taggedHash("BIP0340/challenge", sig.subarray(0, 32), publicKey, message)), N);
+  const R = add(mul(G, s), mul(point, N - e));
+  return R != null && !(R[1] & BigInt(1)) && R[0] == r;
+}
+
+// BIP32 on secp256k1, with hardened steps, and the x-only public key of the result.
+const bip32Secp256k1 = function(seed: Buffer, steps: number[]): Buffer {
+  let I = crypto.createHmac("sha512", "Bitcoin seed").update(seed).digest();
+  let key = num(I.subarray(0, 32));
+  for (const step of steps) {
+    const index = Buffer.alloc(4);
+    index.writeUInt32BE(step);
+    const secret = Buffer.from(key.toString(16).padStart(64, "0"), "hex");
+    I = crypto.createHmac("sha512", I.subarray(32)).update(Buffer.concat([Buffer.from([0]), secret, index])).digest();
+    key = mod(num(I.subarray(0, 32)) + key, N);
+  }
+  const ecdh = crypto.createECDH("secp256k1");
+  ecdh.setPrivateKey(Buffer.from(key.toString(16).padStart(64, "0"), "hex"));
+  return ecdh.getPublicKey(null, "compressed").subarray(1);
+}
+
+const publicKey = bip32Secp256k1(seed, steps);
+
+// BIP340's test vectors, as published in the BIP's test-vectors.csv: index, secret key, public
+// key, auxiliary randomness, message, signature, verification result and comment.
+const vectors = fs.readFileSync(path.join(__dirname, "bip340-test-vectors.csv")).toString()
+  .trim().split("\n").slice(1).map(line => line.split(","));
+
+describe("BIP340 tests", function() {
+  afterEach( async function() {
+    await Axios.post(BASE_URL + "/automation", {version: 1, rules: []});
+    await Axios.delete(BASE_URL + "/events");
+  });
+
+  // The device only signs: it has no BIP340 verification, so the full set of vectors, negative
+  // ones included, checks the verifier the other tests use on the device's signatures.
+  it("verifies BIP340's test vectors", function () {
+    for (const [index, , key, , message, sig, result] of vectors) {
+      expect(verify(Buffer.from(key, "hex"), Buffer.from(message, "hex"), Buffer.from(sig, "hex")), "vector " + index).to.equal(result == "TRUE");
+    }
+  });
+
+  // Only the vectors with a secret key and a 32 byte message can be signed, as the OS only signs
+  // 32 byte messages. The OS draws its own auxiliary randomness, so its signatures differ from
+  // the vectors' ones, but must verify under the same keys.
+  it("signs BIP340's test vectors", async function () {
+    await sendCommandAndAccept(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      for (const [, secret, key, , message] of vectors.filter(v => v[1] != "" && v[4].length == 64)) {
+        const rv = await client.sendChunks(0x00, 0x21, 0, 0, [Buffer.from(secret + message, "hex")]);
+        expect(rv.subarray(0, 32).toString("hex")).to.equal(key.toLowerCase());
+        expect(verify(Buffer.from(key, "hex"), Buffer.from(message, "hex"), rv.subarray(32))).to.equal(true);
+      }
+    }, []);
+  });
+
+  it("exports x-only public keys", async function () {
+    await sendCommandAndAccept(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      const rv = await client.sendChunks(0x00, 0x02, 0, 3, [path]);
+      expect(rv.subarray(1, 1 + rv[0]).toString("hex")).to.equal(publicKey.toString("hex"));
+    }, []);
+  });
+
+  it("signs a tagged hash of the transaction hash", async function () {
+    const txn = transfer();
+    await sendCommandAndAccept(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      const sig = await client.sendChunks(0x00, 0x03, 0, 3, [withLength(txn), path]);
+      const hash = Buffer.from(blake2b(32).update(txn).digest());
+      expect(verify(publicKey, taggedHash("alamgu-example/transaction", hash), sig)).to.equal(true);
+      // Not a signature of the hash itself.
+      expect(verify(publicKey, hash, sig)).to.equal(false);
+    }, [
+      { "header": "Review Transfer", "prompt": "" },
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+      { "header": "To", "prompt": "exa:" + recipient, "paginate": true },
+      { "header": "From", "prompt": "exa:" + publicKey.toString("hex"), "paginate": true },
+      { "text": "Sign Transaction?", "x": 19, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("signs with fresh auxiliary randomness", async function () {
+    const service = "example.com";
+    const nonce = Buffer.alloc(32, 0x6e);
+    const challenge = Buffer.concat([nonce, Buffer.from([service.length]), Buffer.from(service)]);
+    const message = Buffer.concat([Buffer.from("alamgu-example ownership proof"), publicKey, challenge.subarray(32, 33), Buffer.from(service), nonce]);
+    const prompts = [
+      { "header": "Prove Ownership", "prompt": "" },
+      { "header": "Ownership", "prompt": "Prove ownership of exa:" + publicKey.toString("hex") + " to " + service, "paginate": true },
+      { "text": "Sign Proof?", "x": 33, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ];
+    const sigs: Buffer[] = [];
+    for (let i = 0; i < 2; i++) {
+      await sendCommandAndAccept(async (client : any) => {
+        client.sendChunks = client.sendWithBlocks; // Use Block protocol
+        const sig = await client.sendChunks(0x00, 0x05, 0, 3, [challenge, path]);
+        expect(verify(publicKey, taggedHash("alamgu-example/ownership-proof", message), sig)).to.equal(true);
+        sigs.push(sig);
+      }, prompts);
+      await Axios.delete(BASE_URL + "/events");
+    }
+    expect(sigs[0].toString("hex")).to.not.equal(sigs[1].toString("hex"));
+  });
+
+  it("has no X25519 key", async function () {
+    await sendCommandExpectFail(async (client : any) => {
+      client.sendChunks = client.sendWithBlocks; // Use Block protocol
+      await client.sendChunks(0x00, 0x06, 0, 3, [Buffer.alloc(32, 9), path]);
+    });
+  });
+});
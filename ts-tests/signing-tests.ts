@@ -1,4 +1,4 @@
-import { VERSION, sendCommandAndAccept, BASE_URL, sendCommandExpectFail, toggleBlindSigningSettings } from "./common";
+import { VERSION, sendCommandAndAccept, BASE_URL, sendCommandExpectFail, sendCommandExpectStatus, sendCommandAndReject, recipient, transfer, withLength, setBlindSigningSettings, setHashFormatSettings, toggleGroupHashesSettings, toggleExpertModeSettings } from "./common";
 import { expect } from 'chai';
 import { describe, it } from 'mocha';
 import Axios from 'axios';
@@ -28,6 +28,9 @@ function testTransaction(path: string, txn0: string, prompts: any[]) {
   }
 }
 
+// A native transfer of 1.5 EXA on mainnet.
+const nativeTransfer = transfer();
+
 describe("Signing tests", function() {
   before( async function() {
     while(!nacl) await new Promise(r => setTimeout(r, 100));
@@ -42,6 +45,18 @@ describe("Signing tests", function() {
     });
   });
 
+  it("refuses transactions larger than the size limit", async function () {
+    // One byte over the default limit; refused whether or not blind signing is enabled.
+    const txn = Buffer.alloc(16 * 1024 + 1, 0x5a);
+
+    // Only the notice is shown, before any of the transaction is read or reviewed.
+    await sendCommandExpectStatus(async (client : Common) => {
+      await client.signTransaction("44'/535348'/0'", txn);
+    }, 0x6A84, [
+      { "header": "WARNING", "prompt": "Transaction is 16385 bytes, more than the 16384 the app can sign", "paginate": true },
+    ]);
+  });
+
   it("can blind sign a transaction", async function () {
    const path = "44'/535348'/0'";
    const txn = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
@@ -50,13 +65,19 @@ describe("Signing tests", function() {
         "header": "WARNING",
         "prompt": "Transaction not recognized"
       },
+      {
+        "header": "Payload size",
+        "prompt": (txn.length / 2) + " bytes",
+      },
       {
         "header": "Transaction hash",
         "prompt": "yC9c_Zn3cjRXV89tJaT4WjCjXsFF4UQWn2Aq2sHjY-4",
+        "paginate": true,
       },
       {
         "header": "Sign for Address",
-        "prompt": "19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "paginate": true,
       },
       {
         "text": "Blind Sign Transaction?",
@@ -70,11 +91,313 @@ describe("Signing tests", function() {
       },
     ];
 
-    await toggleBlindSigningSettings();
+    await setBlindSigningSettings("Disabled", "Always");
     await Axios.delete(BASE_URL + "/events");
     await testTransaction(path, txn, prompts)();
     await Axios.delete(BASE_URL + "/events");
     // reset back to disabled
-    await toggleBlindSigningSettings();
+    await setBlindSigningSettings("Always", "Disabled");
+  });
+
+  it("only blind signs once in one signature mode", async function () {
+    const path = "44'/535348'/0'";
+    const txn = "0123456789abcdef0123456789abcdef";
+    const hash = Buffer.from(blake2b(32).update(Buffer.from(txn, "hex")).digest()).toString("base64")
+      .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+    const prompts = [
+      {
+        "header": "WARNING",
+        "prompt": "Transaction not recognized"
+      },
+      {
+        "header": "Bytes 1-16",
+        "prompt": txn,
+        "paginate": true,
+      },
+      {
+        "header": "Payload size",
+        "prompt": "16 bytes",
+      },
+      {
+        "header": "Transaction hash",
+        "prompt": hash,
+        "paginate": true,
+      },
+      {
+        "header": "Sign for Address",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "paginate": true,
+      },
+      {
+        "text": "Blind Sign Transaction?",
+        "x": 4,
+        "y": 11,
+      },
+      {
+        "text": "Confirm",
+        "x": 43,
+        "y": 11,
+      },
+    ];
+
+    await setBlindSigningSettings("Disabled", "One signature");
+    await Axios.delete(BASE_URL + "/events");
+    await testTransaction(path, txn, prompts)();
+    await Axios.delete(BASE_URL + "/events");
+    // The signature used up the permission, so there is nothing to reset.
+    await sendCommandExpectFail(async (client : Common) => {
+      await client.signTransaction(path, Buffer.from(txn, "hex"));
+    });
+  });
+
+  it("keeps blind signing on for the session in session mode", async function () {
+    const path = "44'/535348'/0'";
+    const txn = "0123456789abcdef0123456789abcdef";
+    const hash = Buffer.from(blake2b(32).update(Buffer.from(txn, "hex")).digest()).toString("base64")
+      .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+    const prompts = [
+      {
+        "header": "WARNING",
+        "prompt": "Transaction not recognized"
+      },
+      {
+        "header": "Bytes 1-16",
+        "prompt": txn,
+        "paginate": true,
+      },
+      {
+        "header": "Payload size",
+        "prompt": "16 bytes",
+      },
+      {
+        "header": "Transaction hash",
+        "prompt": hash,
+        "paginate": true,
+      },
+      {
+        "header": "Sign for Address",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "paginate": true,
+      },
+      {
+        "text": "Blind Sign Transaction?",
+        "x": 4,
+        "y": 11,
+      },
+      {
+        "text": "Confirm",
+        "x": 43,
+        "y": 11,
+      },
+    ];
+
+    await setBlindSigningSettings("Disabled", "This session");
+    try {
+      for (let i = 0; i < 2; i++) {
+        await Axios.delete(BASE_URL + "/events");
+        await testTransaction(path, txn, prompts)();
+      }
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await setBlindSigningSettings("This session", "Disabled");
+    }
+  });
+
+  it("can reject blind signing from the first review screen", async function () {
+    const path = "44'/535348'/0'";
+    // Too long to be shown as hex, so the review is the first screen.
+    const txn = Buffer.from("0123456789abcdef".repeat(10), "hex");
+
+    await setBlindSigningSettings("Disabled", "One signature");
+    await Axios.delete(BASE_URL + "/events");
+    await sendCommandAndReject(async (client : Common) => {
+      await client.signTransaction(path, txn);
+    });
+    await Axios.delete(BASE_URL + "/events");
+    // Rejecting doesn't use up the permission.
+    await setBlindSigningSettings("One signature", "Disabled");
+  });
+
+  it("shows the hash in the selected format", async function () {
+    const path = "44'/535348'/0'";
+    const txn = "0123456789abcdef0123456789abcdef";
+    const hash = Buffer.from(blake2b(32).update(Buffer.from(txn, "hex")).digest()).toString("hex");
+    const prompts = [
+      {
+        "header": "WARNING",
+        "prompt": "Transaction not recognized"
+      },
+      {
+        "header": "Bytes 1-16",
+        "prompt": txn,
+        "paginate": true,
+      },
+      {
+        "header": "Payload size",
+        "prompt": "16 bytes",
+      },
+      {
+        "header": "Transaction hash",
+        "prompt": hash.match(/.{1,4}/g).join(" "),
+        "paginate": true,
+      },
+      {
+        "header": "Sign for Address",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "paginate": true,
+      },
+      {
+        "text": "Blind Sign Transaction?",
+        "x": 4,
+        "y": 11,
+      },
+      {
+        "text": "Confirm",
+        "x": 43,
+        "y": 11,
+      },
+    ];
+
+    await setBlindSigningSettings("Disabled", "Always");
+    await setHashFormatSettings("Base64url", "Hex");
+    await toggleGroupHashesSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await testTransaction(path, txn, prompts)();
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      // reset back to base64url, ungrouped and blind signing disabled
+      await setHashFormatSettings("Hex", "Base64url");
+      await toggleGroupHashesSettings();
+      await setBlindSigningSettings("Always", "Disabled");
+    }
+  });
+
+  it("shows long payloads as hex in expert mode", async function () {
+    const path = "44'/535348'/0'";
+    const txn = "0123456789abcdef".repeat(10);
+    const hash = Buffer.from(blake2b(32).update(Buffer.from(txn, "hex")).digest()).toString("base64")
+      .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+    const prompts = [
+      { "header": "WARNING", "prompt": "Transaction not recognized" },
+      { "header": "Bytes 1-32", "prompt": txn.slice(0, 64), "paginate": true },
+      { "header": "Bytes 33-64", "prompt": txn.slice(64, 128), "paginate": true },
+      { "header": "Bytes 65-80", "prompt": txn.slice(128), "paginate": true },
+      { "header": "Payload size", "prompt": "80 bytes" },
+      { "header": "Transaction hash", "prompt": hash, "paginate": true },
+      {
+        "header": "Sign for Address",
+        "prompt": "exa:19e2fea57e82293b4fee8120d934f0c5a4907198f8df29e9a153cfd7d9383488",
+        "paginate": true,
+      },
+      { "text": "Blind Sign Transaction?", "x": 4, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ];
+
+    await setBlindSigningSettings("Disabled", "One signature");
+    await toggleExpertModeSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await testTransaction(path, txn, prompts)();
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await toggleExpertModeSettings();
+    }
+  });
+
+  it("signs a transfer from several accounts with one approval", async function () {
+    // 44'/535348'/0' and 44'/535348'/1', little endian
+    const paths = [Buffer.from("032c000080342b088000000080", "hex"), Buffer.from("032c000080342b088001000080", "hex")];
+
+    const keys: Buffer[] = [];
+    await sendCommandAndAccept(async (client : any) => {
+      for (const path of paths) {
+        const rv = await client.sendChunks(0x00, 0x02, 0, 0, [path]);
+        keys.push(rv.subarray(1, 1 + rv[0]));
+      }
+    }, []);
+    expect(keys[0].toString("hex")).to.not.equal(keys[1].toString("hex"));
+
+    await sendCommandAndAccept(async (client : any) => {
+      const sigs = await client.sendChunks(0x00, 0x03, 0, 0, [withLength(nativeTransfer), Buffer.concat(paths)]);
+      expect(sigs.length).to.equal(128);
+      const hash = blake2b(32).update(nativeTransfer).digest();
+      keys.forEach((key, i) => {
+        expect(nacl.crypto_sign_verify_detached(sigs.subarray(64 * i, 64 * (i + 1)), hash, key)).to.equal(true);
+      });
+    }, [
+      { "header": "Review Transfer", "prompt": "" },
+      { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+      { "header": "To", "prompt": "exa:" + recipient, "paginate": true },
+      { "header": "From 1", "prompt": "exa:" + keys[0].toString("hex"), "paginate": true },
+      { "header": "From 2", "prompt": "exa:" + keys[1].toString("hex"), "paginate": true },
+      { "text": "Sign Transaction?", "x": 19, "y": 11 },
+      { "text": "Confirm", "x": 43, "y": 11 },
+    ]);
+  });
+
+  it("shows the largest review, an expert transfer from four accounts", async function () {
+    // 44'/535348'/0' to 44'/535348'/3': with the expert fields, the review has MAX_REVIEW_FIELDS fields.
+    const paths = [0, 1, 2, 3].map(i => Buffer.from("032c000080342b08800" + i + "000080", "hex"));
+    const hash = blake2b(32).update(nativeTransfer).digest();
+    const hashText = Buffer.from(hash).toString("base64")
+      .replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+
+    const keys: Buffer[] = [];
+    await sendCommandAndAccept(async (client : any) => {
+      for (const path of paths) {
+        const rv = await client.sendChunks(0x00, 0x02, 0, 0, [path]);
+        keys.push(rv.subarray(1, 1 + rv[0]));
+      }
+    }, []);
+
+    await toggleExpertModeSettings();
+    await Axios.delete(BASE_URL + "/events");
+    try {
+      await sendCommandAndAccept(async (client : any) => {
+        const sigs = await client.sendChunks(0x00, 0x03, 0, 0, [withLength(nativeTransfer), Buffer.concat(paths)]);
+        expect(sigs.length).to.equal(64 * paths.length);
+        keys.forEach((key, i) => {
+          expect(nacl.crypto_sign_verify_detached(sigs.subarray(64 * i, 64 * (i + 1)), hash, key)).to.equal(true);
+        });
+      }, [
+        { "header": "Review Transfer", "prompt": "" },
+        { "header": "Amount", "prompt": "1.5 EXA", "paginate": true },
+        { "header": "Asset", "prompt": "00".repeat(32), "paginate": true },
+        { "header": "To", "prompt": "exa:" + recipient, "paginate": true },
+        ... keys.map((key, i) => { return { "header": "From " + (i + 1), "prompt": "exa:" + key.toString("hex"), "paginate": true } }),
+        { "header": "Fee", "prompt": "0.0025 EXA", "paginate": true },
+        { "header": "Nonce", "prompt": "42", "paginate": true },
+        { "header": "Expiry", "prompt": "Block 1000000", "paginate": true },
+        { "header": "Chain ID", "prompt": "1", "paginate": true },
+        { "header": "Transaction hash", "prompt": hashText, "paginate": true },
+        { "text": "Sign Transaction?", "x": 19, "y": 11 },
+        { "text": "Confirm", "x": 43, "y": 11 },
+      ]);
+    } finally {
+      await Axios.delete(BASE_URL + "/events");
+      await toggleExpertModeSettings();
+    }
+  });
+
+  it("refuses a transaction parameter with bytes after the transaction", async function () {
+    await sendCommandExpectStatus(async (client : any) => {
+      await client.sendChunks(0x00, 0x03, 0, 0, [Buffer.concat([withLength(nativeTransfer), Buffer.from([0])]), Buffer.from("032c000080342b088000000080", "hex")]);
+    }, 0x6A80);
+  });
+
+  it("refuses more than four inputs", async function () {
+    // 44'/535348'/0' to 44'/535348'/4'
+    const paths = [0, 1, 2, 3, 4].map(i => Buffer.from("032c000080342b08800" + i + "000080", "hex"));
+    await sendCommandExpectStatus(async (client : any) => {
+      await client.sendChunks(0x00, 0x03, 0, 0, [withLength(nativeTransfer), Buffer.concat(paths)]);
+    }, 0x6802);
+  });
+
+  it("refuses the same path for two inputs", async function () {
+    const path = Buffer.from("032c000080342b088000000080", "hex");
+    await sendCommandExpectStatus(async (client : any) => {
+      await client.sendChunks(0x00, 0x03, 0, 0, [withLength(nativeTransfer), Buffer.concat([path, path])]);
+    }, 0x6802);
   });
 });